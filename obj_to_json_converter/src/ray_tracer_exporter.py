@@ -35,13 +35,44 @@ def export_scene(context, filepath):
     look_from = cam_matrix_transformed.to_translation()
     look_dir = cam_matrix_transformed.to_quaternion() @ mathutils.Vector((0.0, 0.0, -1.0))
     look_at = look_from + look_dir
-    scene_data['camera'] = { "width": context.scene.render.resolution_x, "height": context.scene.render.resolution_y, "lookfrom": {"x": look_from.x, "y": look_from.y, "z": look_from.z}, "lookat": {"x": look_at.x, "y": look_at.y, "z": look_at.z}, "vup": {"x": 0, "y": 1, "z": 0}, "vfov": bpy.data.cameras[cam_obj.data.name].angle_y * (180.0 / 3.14159265)}
+    scene_data['camera'] = { "width": context.scene.render.resolution_x, "height": context.scene.render.resolution_y, "lookfrom": {"x": look_from.x, "y": look_from.y, "z": look_from.z}, "lookat": {"x": look_at.x, "y": look_at.y, "z": look_at.z}, "vup": {"x": 0, "y": 1, "z": 0}}
+    cam_data = bpy.data.cameras[cam_obj.data.name]
+    # Export the lens and sensor as Blender sees them so the framing matches, including off-axis shift.
+    scene_data['camera'].update({"focal_length": cam_data.lens, "sensor_width": cam_data.sensor_width, "sensor_height": cam_data.sensor_height, "sensor_fit": cam_data.sensor_fit.capitalize(), "shift_x": cam_data.shift_x, "shift_y": cam_data.shift_y})
+    if cam_data.dof.use_dof:
+        # Blender's aperture diameter is the focal length over the f-stop, both in millimetres.
+        scene_data['camera']['aperture'] = cam_data.lens / cam_data.dof.aperture_fstop * 0.001
+        scene_data['camera']['aperture_blades'] = cam_data.dof.aperture_blades
+        scene_data['camera']['aperture_rotation'] = cam_data.dof.aperture_rotation * (180.0 / 3.14159265)
+        if cam_data.dof.focus_object:
+            focus_pos = transform_matrix @ cam_data.dof.focus_object.matrix_world.to_translation()
+            scene_data['camera']['focus_distance'] = (focus_pos - look_from).dot(look_dir)
+        else:
+            scene_data['camera']['focus_distance'] = cam_data.dof.focus_distance
+    # Blender's exposure is an offset in stops applied before the view transform.
+    if context.scene.view_settings.exposure != 0.0:
+        scene_data['camera']['exposure'] = {"compensation": context.scene.view_settings.exposure}
     world = context.scene.world
     if world: bg_color = world.color; scene_data['background_color'] = [int(c * 255) for c in bg_color[:3]]; scene_data['ambient_light'] = {"x": bg_color[0] * 0.1, "y": bg_color[1] * 0.1, "z": bg_color[2] * 0.1}
     else: scene_data['background_color'] = [10, 10, 20]; scene_data['ambient_light'] = {"x": 0.1, "y": 0.1, "z": 0.1}
     scene_data['lights'] = []
     for light_obj in [obj for obj in context.scene.objects if obj.type == 'LIGHT']:
-        if light_obj.data.type == 'POINT': light_pos = transform_matrix @ light_obj.location; intensity = light_obj.data.energy / 100.0; scene_data['lights'].append({"position": {"x": light_pos.x, "y": light_pos.y, "z": light_pos.z}, "intensity": intensity})
+        light_pos = transform_matrix @ light_obj.location; light_color = light_obj.data.color
+        color = {"x": light_color[0], "y": light_color[1], "z": light_color[2]}
+        if light_obj.data.type == 'POINT': scene_data['lights'].append({"type": "Point", "position": {"x": light_pos.x, "y": light_pos.y, "z": light_pos.z}, "color": color, "power": light_obj.data.energy})
+        elif light_obj.data.type == 'SPOT':
+            # Blender spot lights point down their local -Z axis; spot_size is the full cone angle.
+            spot_dir = (transform_matrix @ light_obj.matrix_world).to_quaternion() @ mathutils.Vector((0.0, 0.0, -1.0))
+            outer_angle = light_obj.data.spot_size * 0.5 * (180.0 / 3.14159265)
+            inner_angle = outer_angle * (1.0 - light_obj.data.spot_blend)
+            # Blender rates spot power as if the light radiated over the whole sphere.
+            intensity = light_obj.data.energy / (4.0 * 3.14159265)
+            scene_data['lights'].append({"type": "Spot", "position": {"x": light_pos.x, "y": light_pos.y, "z": light_pos.z}, "direction": {"x": spot_dir.x, "y": spot_dir.y, "z": spot_dir.z}, "inner_angle": inner_angle, "outer_angle": outer_angle, "color": color, "intensity": intensity})
+        elif light_obj.data.type == 'SUN':
+            # Sun strength is already an irradiance in W/m^2 and angle is the full angular diameter.
+            sun_dir = (transform_matrix @ light_obj.matrix_world).to_quaternion() @ mathutils.Vector((0.0, 0.0, -1.0))
+            sun_color = light_obj.data.color
+            scene_data['lights'].append({"type": "Directional", "direction": {"x": sun_dir.x, "y": sun_dir.y, "z": sun_dir.z}, "color": {"x": sun_color[0], "y": sun_color[1], "z": sun_color[2]}, "irradiance": light_obj.data.energy, "angular_diameter": light_obj.data.angle * (180.0 / 3.14159265)})
 
     scene_data['objects'] = []
     depsgraph = context.evaluated_depsgraph_get()
@@ -1,10 +1,126 @@
-use crate::math::{ray::Ray, vec3::Vec3};
+use crate::{
+    lens::LensSystem,
+    math::{
+        ray::Ray,
+        sampling::{sample_concentric_disk, sample_regular_polygon},
+        vec3::Vec3,
+    },
+};
+use std::f64::consts::PI;
+
+// Where on the film and the lens a camera ray starts, as uniform samples in
+// [0, 1)^2. Film coordinates run left to right and bottom to top. `time`
+// picks the moment within the shutter interval, from 0 to 1.
+pub struct CameraSample {
+    pub film: (f64, f64),
+    pub lens: (f64, f64),
+    pub time: f64,
+}
+
+#[derive(Clone, Copy)]
+pub enum Projection {
+    Perspective,
+    // Parallel rays through a view `view_height` units tall.
+    Orthographic { view_height: f64 },
+    // A full 360 by 180 degree latitude-longitude panorama centred on the
+    // view direction, in the layout `EnvironmentMap` reads.
+    Equirectangular,
+    // A circular fisheye inscribed in the shorter side of the image, covering
+    // `fov` radians across the circle.
+    Fisheye { mapping: FisheyeMapping, fov: f64 },
+    // Six 90 degree faces in a 3x2 grid: +X, -X, +Y on the top row and -Y,
+    // +Z, -Z below, in camera space (X right, Y up, Z backwards).
+    Cubemap,
+}
+
+// How the angle from the view direction maps to distance from the centre of a
+// fisheye image.
+#[derive(Clone, Copy)]
+pub enum FisheyeMapping {
+    // Distance proportional to the angle.
+    Equidistant,
+    // Equal areas in the image cover equal solid angles.
+    Equisolid,
+}
+
+// Which side of the image the sensor size applies to, as in Blender. Auto
+// uses the sensor width for whichever side is longer.
+#[derive(Clone, Copy)]
+pub enum SensorFit {
+    Auto,
+    Horizontal,
+    Vertical,
+}
+
+// Where each eye's view goes in the output image.
+#[derive(Clone, Copy)]
+pub enum StereoLayout {
+    // Left eye on the left.
+    SideBySide,
+    // Left eye on top.
+    TopBottom,
+}
+
+#[derive(Clone, Copy)]
+pub struct Stereo {
+    // Distance between the eyes in scene units.
+    pub interpupillary_distance: f64,
+    // Distance at which the eyes' views line up, i.e. objects there appear at
+    // screen depth. Defaults to the focus distance.
+    pub convergence: Option<f64>,
+    pub layout: StereoLayout,
+}
+
+// A traced lens system in front of a film of the given size, in scene units.
+struct RealisticLens {
+    system: LensSystem,
+    film_width: f64,
+    film_height: f64,
+    // Evens out the light the lens lets through so the centre of the image is
+    // as bright as with a pinhole.
+    gain: f64,
+}
+
+// Forward, right and up axes of each cubemap face, in camera space.
+const CUBEMAP_FACES: [[[f64; 3]; 3]; 6] = [
+    [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]],
+    [[-1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]],
+    [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+    [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]],
+    [[0.0, 0.0, 1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+    [[0.0, 0.0, -1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+];
 
 pub struct Camera {
+    projection: Projection,
     origin: Vec3<f64>,
-    lower_left_corner: Vec3<f64>,
-    horizontal: Vec3<f64>,
-    vertical: Vec3<f64>,
+    // Camera basis: right, up, and backwards from the view direction.
+    u: Vec3<f64>,
+    v: Vec3<f64>,
+    w: Vec3<f64>,
+    // Size of the image plane one unit in front of the camera.
+    viewport_width: f64,
+    viewport_height: f64,
+    sensor_fit: SensorFit,
+    // Off-axis offset of the image, in units of the fitted side.
+    shift_x: f64,
+    shift_y: f64,
+    // Zero for a pinhole.
+    lens_radius: f64,
+    // Distance to the plane that is in perfect focus.
+    focus_distance: f64,
+    // Zero for a round aperture.
+    aperture_blades: u32,
+    blade_rotation: f64,
+    stereo: Option<Stereo>,
+    // Scene times at which the shutter opens and closes.
+    shutter_open: f64,
+    shutter_close: f64,
+    // Multiplies radiance before it is written out.
+    exposure: f64,
+    // Replaces the perspective projection and thin lens when set.
+    realistic_lens: Option<RealisticLens>,
+    // Size of the whole output image, which holds both eyes in stereo.
     pub width: u32,
     pub height: u32,
 }
@@ -29,25 +145,597 @@ impl Camera {
         let u = vup.cross(&w).normalize();
         let v = w.cross(&u);
 
-        let origin = lookfrom;
-        let horizontal = u * viewport_width;
-        let vertical = v * viewport_height;
-
-        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - w;
-
         Self {
-            origin,
-            lower_left_corner,
-            horizontal,
-            vertical,
+            projection: Projection::Perspective,
+            origin: lookfrom,
+            u,
+            v,
+            w,
+            viewport_width,
+            viewport_height,
+            sensor_fit: SensorFit::Auto,
+            shift_x: 0.0,
+            shift_y: 0.0,
+            lens_radius: 0.0,
+            focus_distance: (lookfrom - lookat).length(),
+            aperture_blades: 0,
+            blade_rotation: 0.0,
+            stereo: None,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            exposure: 1.0,
+            realistic_lens: None,
             width,
             height,
         }
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
-        let direction =
-            self.lower_left_corner + self.horizontal * u + self.vertical * v - self.origin;
-        Ray::new(self.origin, direction.normalize())
+    // Sets the field of view from a focal length and sensor size, all in
+    // millimetres, in place of `vfov`.
+    pub fn with_sensor(
+        mut self,
+        focal_length: f64,
+        sensor_width: f64,
+        sensor_height: f64,
+        fit: SensorFit,
+    ) -> Self {
+        let aspect_ratio = self.width as f64 / self.height as f64;
+        self.sensor_fit = fit;
+        let horizontal = match fit {
+            SensorFit::Auto => aspect_ratio >= 1.0,
+            SensorFit::Horizontal => true,
+            SensorFit::Vertical => false,
+        };
+        if horizontal {
+            self.viewport_width = sensor_width / focal_length;
+            self.viewport_height = self.viewport_width / aspect_ratio;
+        } else {
+            let size = match fit {
+                SensorFit::Vertical => sensor_height,
+                _ => sensor_width,
+            };
+            self.viewport_height = size / focal_length;
+            self.viewport_width = self.viewport_height * aspect_ratio;
+        }
+        self
+    }
+
+    // Sets the horizontal field of view, in degrees, in place of `vfov`.
+    pub fn with_hfov(mut self, hfov_degrees: f64) -> Self {
+        let aspect_ratio = self.width as f64 / self.height as f64;
+        self.viewport_width = 2.0 * (hfov_degrees.to_radians() / 2.0).tan();
+        self.viewport_height = self.viewport_width / aspect_ratio;
+        self
+    }
+
+    // Moves the image off-axis without tilting the view, which keeps vertical
+    // lines parallel when framing tall buildings. Shifts are fractions of the
+    // side the sensor is fitted to.
+    pub fn with_lens_shift(mut self, shift_x: f64, shift_y: f64) -> Self {
+        self.shift_x = shift_x;
+        self.shift_y = shift_y;
+        self
+    }
+
+    fn fits_horizontally(&self) -> bool {
+        match self.sensor_fit {
+            SensorFit::Auto => self.viewport_width >= self.viewport_height,
+            SensorFit::Horizontal => true,
+            SensorFit::Vertical => false,
+        }
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    // Turns the pinhole into a thin lens with an aperture `aperture` wide.
+    // Without a focus distance the camera focuses on `lookat`.
+    pub fn with_lens(mut self, aperture: f64, focus_distance: Option<f64>) -> Self {
+        self.lens_radius = aperture.max(0.0) / 2.0;
+        if let Some(distance) = focus_distance {
+            self.focus_distance = distance;
+        }
+        self
+    }
+
+    // Shapes the aperture as a regular polygon, which shows in out-of-focus
+    // highlights. Fewer than three blades keeps it round.
+    pub fn with_aperture_blades(mut self, blades: u32, rotation_degrees: f64) -> Self {
+        self.aperture_blades = if blades >= 3 { blades } else { 0 };
+        self.blade_rotation = rotation_degrees.to_radians();
+        self
+    }
+
+    // Renders both eyes into one image, doubling its width or height to fit
+    // them. Each eye keeps the field of view of the original image.
+    pub fn with_stereo(mut self, stereo: Stereo) -> Self {
+        match stereo.layout {
+            StereoLayout::SideBySide => self.width *= 2,
+            StereoLayout::TopBottom => self.height *= 2,
+        }
+        self.stereo = Some(stereo);
+        self
+    }
+
+    // Spreads rays over the interval between `open` and `close` so that
+    // moving objects blur.
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Self {
+        self.shutter_open = open;
+        self.shutter_close = close.max(open);
+        self
+    }
+
+    pub fn with_exposure(mut self, exposure: f64) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    // Traces rays through `system` onto a film with the given diagonal in
+    // scene units, which together set the field of view. The system should
+    // already be focused.
+    pub fn with_lens_system(mut self, system: LensSystem, film_diagonal: f64) -> Self {
+        let aspect_ratio = self.viewport_width / self.viewport_height;
+        let film_height = film_diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+
+        // Fraction of the rear element that the film centre sees through the
+        // whole system.
+        const STRATA: usize = 32;
+        let radius = system.rear_aperture_radius();
+        let centre = Vec3::new(0.0, 0.0, 0.0);
+        let passed = (0..STRATA * STRATA)
+            .filter(|i| {
+                let u = ((i % STRATA) as f64 + 0.5) / STRATA as f64;
+                let v = ((i / STRATA) as f64 + 0.5) / STRATA as f64;
+                let (x, y) = sample_concentric_disk((u, v));
+                let rear = Vec3::new(x * radius, y * radius, system.rear_z());
+                system.trace_from_film(centre, rear - centre).is_some()
+            })
+            .count();
+        let transmission = passed as f64 / (STRATA * STRATA) as f64;
+
+        self.realistic_lens = Some(RealisticLens {
+            system,
+            film_width: film_height * aspect_ratio,
+            film_height,
+            gain: if transmission > 0.0 {
+                1.0 / transmission
+            } else {
+                1.0
+            },
+        });
+        self
+    }
+
+    // Scale applied to radiance before output, including any compensation
+    // for light lost in a traced lens.
+    pub fn exposure(&self) -> f64 {
+        self.exposure * self.realistic_lens.as_ref().map_or(1.0, |lens| lens.gain)
+    }
+
+    // Film coordinates for a point on the image, in pixels from its top left
+    // corner. Planar views spread the film over one pixel fewer than the
+    // image, which is how scenes have always been framed; panoramas and
+    // stereo pairs need the film to cover the image exactly to line up.
+    pub fn film_position(&self, x: f64, y: f64) -> (f64, f64) {
+        let planar = self.realistic_lens.is_some()
+            || matches!(
+                self.projection,
+                Projection::Perspective | Projection::Orthographic { .. }
+            );
+        let (width, height) = if planar && self.stereo.is_none() {
+            ((self.width - 1).max(1), (self.height - 1).max(1))
+        } else {
+            (self.width, self.height)
+        };
+        (x / width as f64, 1.0 - y / height as f64)
+    }
+
+    // Returns None where the film has no view, such as outside the circle of
+    // a fisheye.
+    pub fn get_ray(&self, sample: &CameraSample) -> Option<Ray> {
+        let ((s, t), eye) = self.split_eyes(sample.film);
+        let time = self.shutter_open + (self.shutter_close - self.shutter_open) * sample.time;
+
+        if let Some(lens) = &self.realistic_lens {
+            return self.trace_lens(lens, s, t, eye, sample.lens, time);
+        }
+
+        // Lens shift slides the film within the planar projections.
+        let fitted_size = if self.fits_horizontally() {
+            self.viewport_width
+        } else {
+            self.viewport_height
+        };
+        let sx = s - 0.5 + self.shift_x * fitted_size / self.viewport_width;
+        let sy = t - 0.5 + self.shift_y * fitted_size / self.viewport_height;
+
+        // Ray origin and direction for a pinhole at the centre of the head.
+        let planar = matches!(
+            self.projection,
+            Projection::Perspective | Projection::Orthographic { .. }
+        );
+        let (origin, direction) = match self.projection {
+            Projection::Perspective => (
+                self.origin,
+                self.u * (sx * self.viewport_width) + self.v * (sy * self.viewport_height) - self.w,
+            ),
+            Projection::Orthographic { view_height } => {
+                let view_width = view_height * self.viewport_width / self.viewport_height;
+                (
+                    self.origin + self.u * (sx * view_width) + self.v * (sy * view_height),
+                    self.w * -1.0,
+                )
+            }
+            _ => {
+                let [x, y, z] = self.panorama_direction(s, t)?;
+                (self.origin, self.u * x + self.v * y + self.w * z)
+            }
+        };
+
+        let (origin, direction) = match self.stereo {
+            Some(stereo) => self.offset_eye(&stereo, eye, s, origin, direction, planar),
+            None => (origin, direction),
+        };
+
+        // Panoramas are always pinholes.
+        if self.lens_radius <= 0.0 || !planar {
+            return Some(Ray::new(origin, direction.normalize(), time));
+        }
+
+        // Rays through any point of the lens meet again on the focus plane.
+        let focus_point = origin + direction * (self.focus_distance / -direction.dot(&self.w));
+        let (lx, ly) = if self.aperture_blades > 0 {
+            sample_regular_polygon(self.aperture_blades, self.blade_rotation, sample.lens)
+        } else {
+            sample_concentric_disk(sample.lens)
+        };
+        let lens_point = origin + (self.u * lx + self.v * ly) * self.lens_radius;
+        Some(Ray::new(
+            lens_point,
+            (focus_point - lens_point).normalize(),
+            time,
+        ))
+    }
+
+    // Starts a ray on the film, aims it at a point on the rear element and
+    // follows it out through the lens. The image forms upside down, so the
+    // film is flipped to keep the output upright.
+    fn trace_lens(
+        &self,
+        lens: &RealisticLens,
+        s: f64,
+        t: f64,
+        eye: f64,
+        lens_sample: (f64, f64),
+        time: f64,
+    ) -> Option<Ray> {
+        let film = Vec3::new(
+            -(s - 0.5) * lens.film_width,
+            -(t - 0.5) * lens.film_height,
+            0.0,
+        );
+        let (x, y) = sample_concentric_disk(lens_sample);
+        let radius = lens.system.rear_aperture_radius();
+        let rear = Vec3::new(x * radius, y * radius, lens.system.rear_z());
+        let (o, d) = lens.system.trace_from_film(film, rear - film)?;
+
+        // Lens space looks down -z, which is -w in the scene.
+        let eye_offset = self
+            .stereo
+            .map_or(0.0, |stereo| eye * stereo.interpupillary_distance);
+        let origin = self.origin + self.u * (o.x + eye_offset) + self.v * o.y + self.w * o.z;
+        let direction = self.u * d.x + self.v * d.y + self.w * d.z;
+        Some(Ray::new(origin, direction.normalize(), time))
+    }
+
+    // Maps film coordinates on the whole image to those of one eye, with -0.5
+    // for the left eye, 0.5 for the right and 0 without stereo.
+    fn split_eyes(&self, (s, t): (f64, f64)) -> ((f64, f64), f64) {
+        match self.stereo.map(|stereo| stereo.layout) {
+            None => ((s, t), 0.0),
+            Some(StereoLayout::SideBySide) if s < 0.5 => ((s * 2.0, t), -0.5),
+            Some(StereoLayout::SideBySide) => ((s * 2.0 - 1.0, t), 0.5),
+            Some(StereoLayout::TopBottom) if t >= 0.5 => ((s, t * 2.0 - 1.0), -0.5),
+            Some(StereoLayout::TopBottom) => ((s, t * 2.0), 0.5),
+        }
+    }
+
+    // Moves a ray from the centre of the head to one eye, turning it so both
+    // eyes meet at the convergence distance.
+    fn offset_eye(
+        &self,
+        stereo: &Stereo,
+        eye: f64,
+        s: f64,
+        origin: Vec3<f64>,
+        direction: Vec3<f64>,
+        planar: bool,
+    ) -> (Vec3<f64>, Vec3<f64>) {
+        let side = match self.projection {
+            // Omni-directional stereo: the eyes turn with the head, so each
+            // column of the panorama has its own sideways axis.
+            Projection::Equirectangular => {
+                let phi = (s - 0.5) * 2.0 * PI;
+                self.u * phi.cos() + self.w * phi.sin()
+            }
+            _ => self.u,
+        };
+        let eye_origin = origin + side * (eye * stereo.interpupillary_distance);
+
+        // Planar projections converge on a plane, giving off-axis frusta
+        // rather than toed-in eyes; panoramas converge on a sphere.
+        let convergence = stereo.convergence.unwrap_or(self.focus_distance);
+        let target = if planar {
+            origin + direction * (convergence / -direction.dot(&self.w))
+        } else {
+            origin + direction.normalize() * convergence
+        };
+        (eye_origin, target - eye_origin)
+    }
+
+    // Direction in camera space for the panoramic projections.
+    fn panorama_direction(&self, s: f64, t: f64) -> Option<[f64; 3]> {
+        match self.projection {
+            Projection::Equirectangular => {
+                let phi = (s - 0.5) * 2.0 * PI;
+                let latitude = (t - 0.5) * PI;
+                Some([
+                    phi.sin() * latitude.cos(),
+                    latitude.sin(),
+                    -phi.cos() * latitude.cos(),
+                ])
+            }
+            Projection::Fisheye { mapping, fov } => {
+                // Offsets from the centre, scaled so the circle has radius one.
+                let scale = self.viewport_width.min(self.viewport_height);
+                let x = (s - 0.5) * 2.0 * self.viewport_width / scale;
+                let y = (t - 0.5) * 2.0 * self.viewport_height / scale;
+                let r = (x * x + y * y).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * fov / 2.0,
+                    FisheyeMapping::Equisolid => {
+                        2.0 * (r * (fov / 4.0).sin()).clamp(-1.0, 1.0).asin()
+                    }
+                };
+                let phi = y.atan2(x);
+                Some([
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    -theta.cos(),
+                ])
+            }
+            Projection::Cubemap => {
+                let column = ((s * 3.0) as usize).min(2);
+                let row = if t >= 0.5 { 0 } else { 1 };
+                let [forward, right, up] = CUBEMAP_FACES[row * 3 + column];
+
+                // Position within the face, from -1 to 1.
+                let a = (s * 3.0 - column as f64) * 2.0 - 1.0;
+                let b = (t * 2.0 - (1 - row) as f64) * 2.0 - 1.0;
+                Some([0, 1, 2].map(|i| forward[i] + right[i] * a + up[i] * b))
+            }
+            Projection::Perspective | Projection::Orthographic { .. } => None,
+        }
+    }
+}
+
+// Exposure value at ISO 100 for a photographic exposure, with the shutter
+// speed in seconds.
+pub fn exposure_value(f_stop: f64, shutter_speed: f64, iso: f64) -> f64 {
+    (f_stop * f_stop / shutter_speed * 100.0 / iso).log2()
+}
+
+// Scale that brings scene luminance metered at `ev100` to the top of the
+// output range, following the saturation-based sensitivity of ISO 12232 (the
+// factor 1.2 is 78 / (100 * 0.65) for a typical lens and sensor).
+pub fn exposure_scale(ev100: f64) -> f64 {
+    1.0 / (1.2 * 2.0f64.powf(ev100))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lens_rays_converge_on_focus_plane() {
+        let camera = Camera::new(
+            160,
+            90,
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -4.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+        )
+        .with_lens(0.5, None)
+        .with_aperture_blades(6, 0.0);
+
+        let focus_point = |lens| {
+            let ray = camera
+                .get_ray(&CameraSample {
+                    film: (0.3, 0.8),
+                    lens,
+                    time: 0.0,
+                })
+                .unwrap();
+            let t = -4.0 / ray.direction.z;
+            ray.origin + ray.direction * t
+        };
+
+        let a = focus_point((0.1, 0.2));
+        let b = focus_point((0.9, 0.7));
+        assert!((a - b).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_orthographic_rays_are_parallel() {
+        let camera = Camera::new(
+            200,
+            100,
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+        )
+        .with_projection(Projection::Orthographic { view_height: 2.0 });
+
+        let corner = camera
+            .get_ray(&CameraSample {
+                film: (1.0, 1.0),
+                lens: (0.5, 0.5),
+                time: 0.0,
+            })
+            .unwrap();
+        assert!((corner.direction - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-12);
+        assert!((corner.origin - Vec3::new(2.0, 1.0, 0.0)).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_sensor_fit_and_lens_shift() {
+        let camera = |width, height, fit| {
+            Camera::new(
+                width,
+                height,
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+            )
+            .with_sensor(50.0, 36.0, 24.0, fit)
+        };
+        let edge = |camera: &Camera, film| {
+            let ray = camera
+                .get_ray(&CameraSample {
+                    film,
+                    lens: (0.5, 0.5),
+                    time: 0.0,
+                })
+                .unwrap();
+            ray.direction / -ray.direction.z
+        };
+
+        // Auto puts the sensor width across the longer side of the image.
+        let landscape = camera(300, 200, SensorFit::Auto);
+        assert!((edge(&landscape, (1.0, 0.5)).x - 0.36).abs() < 1e-12);
+        let portrait = camera(200, 300, SensorFit::Auto);
+        assert!((edge(&portrait, (0.5, 1.0)).y - 0.36).abs() < 1e-12);
+        let vertical = camera(300, 200, SensorFit::Vertical);
+        assert!((edge(&vertical, (0.5, 1.0)).y - 0.24).abs() < 1e-12);
+
+        // A shift of 0.5 moves the centre of the image to the old right edge.
+        let shifted = camera(300, 200, SensorFit::Auto).with_lens_shift(0.5, 0.0);
+        assert!((edge(&shifted, (0.5, 0.5)).x - 0.36).abs() < 1e-12);
+
+        // 90 degrees across reaches 45 degrees either side of the centre.
+        let wide = camera(300, 200, SensorFit::Auto).with_hfov(90.0);
+        assert!((edge(&wide, (1.0, 0.5)).x - 1.0).abs() < 1e-12);
+        assert!((edge(&wide, (0.5, 1.0)).y - 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_film_position_keeps_planar_framing() {
+        let camera = Camera::new(
+            300,
+            200,
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+        );
+        assert_eq!(camera.film_position(299.0, 199.0), (1.0, 0.0));
+
+        let panorama = camera.with_projection(Projection::Equirectangular);
+        assert_eq!(panorama.film_position(300.0, 200.0), (1.0, 0.0));
+        assert_eq!(panorama.film_position(150.0, 100.0), (0.5, 0.5));
+    }
+
+    #[test]
+    fn test_panoramas_look_forward_at_centre() {
+        let camera = |projection| {
+            Camera::new(
+                300,
+                200,
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                40.0,
+            )
+            .with_projection(projection)
+        };
+        let direction = |camera: &Camera, film| {
+            camera
+                .get_ray(&CameraSample {
+                    film,
+                    lens: (0.5, 0.5),
+                    time: 0.0,
+                })
+                .map(|ray| ray.direction)
+        };
+        let forward = Vec3::new(1.0, 0.0, 0.0);
+
+        let equirect = camera(Projection::Equirectangular);
+        let d = direction(&equirect, (0.5, 0.5)).unwrap();
+        assert!((d - forward).length() < 1e-12);
+
+        let fisheye = camera(Projection::Fisheye {
+            mapping: FisheyeMapping::Equisolid,
+            fov: PI,
+        });
+        let d = direction(&fisheye, (0.5, 0.5)).unwrap();
+        assert!((d - forward).length() < 1e-12);
+        // Corners fall outside the image circle.
+        assert!(direction(&fisheye, (0.0, 0.0)).is_none());
+
+        // The -Z face is the bottom right of the grid.
+        let cubemap = camera(Projection::Cubemap);
+        let d = direction(&cubemap, (5.0 / 6.0, 0.25)).unwrap();
+        assert!((d - forward).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_stereo_eyes_meet_at_convergence() {
+        let camera = Camera::new(
+            100,
+            100,
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+        )
+        .with_stereo(Stereo {
+            interpupillary_distance: 0.1,
+            convergence: Some(3.0),
+            layout: StereoLayout::SideBySide,
+        });
+        assert_eq!((camera.width, camera.height), (200, 100));
+
+        // The same point of each eye's view, in the left and right halves.
+        let hit = |s| {
+            let ray = camera
+                .get_ray(&CameraSample {
+                    film: (s, 0.6),
+                    lens: (0.5, 0.5),
+                    time: 0.0,
+                })
+                .unwrap();
+            ray.origin + ray.direction * (-3.0 / ray.direction.z)
+        };
+        let (left, right) = (hit(0.15), hit(0.65));
+        assert!((left - right).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_sunny_sixteen_exposure() {
+        // f/16 at 1/100 s and ISO 100 is about EV 14.6.
+        let ev = exposure_value(16.0, 0.01, 100.0);
+        assert!((ev - 14.64).abs() < 0.01);
+        // Doubling the ISO gains a stop.
+        assert!((exposure_value(16.0, 0.01, 200.0) - (ev - 1.0)).abs() < 1e-12);
+        assert!((exposure_scale(0.0) - 1.0 / 1.2).abs() < 1e-12);
     }
 }
@@ -1,22 +1,135 @@
 use crate::{
-    camera::Camera,
+    camera::{
+        Camera, FisheyeMapping, Projection, SensorFit, Stereo, StereoLayout, exposure_scale,
+        exposure_value,
+    },
+    environment::{Environment, EnvironmentLight, EnvironmentMap, SolidColor},
+    filter::{Filter, FilterKind},
     hittable::Hittable,
-    light::PointLight,
+    ies::IesProfile,
+    lens::LensSystem,
+    light::{
+        AreaSampling, DirectionalLight, DiskLight, Light, PointLight, RectLight, SphereLight,
+        SpotLight,
+    },
     material::{Lambertian, Material},
     math::vec3::Vec3,
-    scene::Scene,
-    shapes::{mesh::Mesh, sphere::Sphere},
+    ply::load_ply,
+    scene::{Scene, SceneObject},
+    shapes::{
+        animated::{Animated, Keyframe},
+        mesh::Mesh,
+        point_cloud::{PointCloud, SplatShape},
+        sphere::Sphere,
+    },
+    sky::PreethamSky,
 };
 use serde::Deserialize;
+use serde::de::IgnoredAny;
+use std::collections::HashSet;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::path::Path;
 use std::sync::Arc;
 
 #[derive(Deserialize)]
 struct SceneDef {
     camera: CameraDef,
+    // Ignored when an `environment` is given.
+    #[serde(default)]
     background_color: RgbDef,
+    environment: Option<EnvironmentDef>,
     ambient_light: Vec3<f64>,
-    lights: Vec<PointLight>,
+    lights: Vec<LightDef>,
     objects: Vec<ObjectDef>,
+    // Overridden by `--filter` on the command line.
+    filter: Option<FilterDef>,
+}
+
+#[derive(Deserialize)]
+struct FilterDef {
+    #[serde(rename = "type")]
+    kind: FilterKindDef,
+    // Defaults to a width suited to the filter.
+    radius: Option<f64>,
+}
+
+#[derive(Deserialize)]
+enum FilterKindDef {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FilterDef {
+    fn build(self) -> Result<Filter, Box<dyn Error>> {
+        let kind = match self.kind {
+            FilterKindDef::Box => FilterKind::Box,
+            FilterKindDef::Tent => FilterKind::Tent,
+            FilterKindDef::Gaussian => FilterKind::Gaussian,
+            FilterKindDef::Mitchell => FilterKind::Mitchell,
+            FilterKindDef::Lanczos => FilterKind::Lanczos,
+        };
+        Filter::new(kind, self.radius)
+    }
+}
+
+// Lights the scene from infinitely far away and is seen by rays that miss
+// every object.
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum EnvironmentDef {
+    Hdri(HdriDef),
+    Sky(SkyDef),
+}
+
+#[derive(Deserialize)]
+struct HdriDef {
+    // Equirectangular `.hdr` or `.exr` image, relative to the scene file.
+    path: String,
+    // Rotation about the up axis in degrees.
+    #[serde(default)]
+    rotation: f64,
+    #[serde(default = "default_intensity")]
+    intensity: f64,
+}
+
+// A Preetham daylight sky. Radiance is in kcd/m^2 times `intensity`.
+#[derive(Deserialize)]
+struct SkyDef {
+    // Degrees above the horizon.
+    sun_elevation: f64,
+    // Degrees clockwise from -Z towards +X.
+    #[serde(default)]
+    sun_azimuth: f64,
+    #[serde(default = "default_turbidity")]
+    turbidity: f64,
+    #[serde(default = "default_intensity")]
+    intensity: f64,
+    // Adds a directional light for the sun matching the sky.
+    #[serde(default = "default_true")]
+    sun: bool,
+    // Darkens the reflection of the horizon used below it.
+    #[serde(default = "default_ground_albedo")]
+    ground_albedo: f64,
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+fn default_ground_albedo() -> f64 {
+    0.3
+}
+
+fn default_true() -> bool {
+    true
 }
 
 #[derive(Deserialize)]
@@ -26,14 +139,330 @@ struct CameraDef {
     lookfrom: Vec3<f64>,
     lookat: Vec3<f64>,
     vup: Vec3<f64>,
-    vfov: f64,
+    // Vertical or horizontal field of view in degrees. Exactly one of these
+    // or `focal_length` sets the field of view.
+    vfov: Option<f64>,
+    hfov: Option<f64>,
+    // Focal length in millimetres, used with the sensor size below.
+    focal_length: Option<f64>,
+    #[serde(default = "default_sensor_width")]
+    sensor_width: f64,
+    #[serde(default = "default_sensor_height")]
+    sensor_height: f64,
+    #[serde(default)]
+    sensor_fit: SensorFitDef,
+    // Off-axis shift as a fraction of the fitted side of the image.
+    #[serde(default)]
+    shift_x: f64,
+    #[serde(default)]
+    shift_y: f64,
+    #[serde(default)]
+    projection: ProjectionDef,
+    // Lens diameter in scene units; zero gives a pinhole with everything in
+    // focus.
+    #[serde(default)]
+    aperture: f64,
+    // Distance to the plane in focus. Focuses on `lookat` when omitted.
+    focus_distance: Option<f64>,
+    // Number of aperture blades for polygonal bokeh; round when below three.
+    #[serde(default)]
+    aperture_blades: u32,
+    // Rotation of the blades in degrees.
+    #[serde(default)]
+    aperture_rotation: f64,
+    // Renders a left and right eye into one image.
+    stereo: Option<StereoDef>,
+    // Scene times between which the shutter is open, for motion blur.
+    #[serde(default)]
+    shutter_open: f64,
+    #[serde(default)]
+    shutter_close: f64,
+    // Scales the rendered radiance like a physical camera would.
+    exposure: Option<ExposureDef>,
+    // Traces rays through real lens elements instead of a pinhole or thin
+    // lens; the field of view, `projection` and `aperture` are then ignored.
+    realistic_lens: Option<RealisticLensDef>,
+}
+
+#[derive(Deserialize)]
+struct RealisticLensDef {
+    // Lens prescription, relative to the scene file.
+    path: String,
+    // Film diagonal in millimetres; the default is 35mm full frame.
+    #[serde(default = "default_film_diagonal")]
+    film_diagonal: f64,
+    // Stops the aperture down from its full size, in millimetres.
+    aperture_diameter: Option<f64>,
+}
+
+fn default_film_diagonal() -> f64 {
+    43.27
+}
+
+// Either an explicit `ev` or an `f_stop` and `shutter_speed` with `iso`. These
+// only set the brightness: depth of field comes from `aperture` and motion
+// blur from the shutter interval. Radiance is metered as cd/m^2, so a sky
+// (in kcd/m^2) needs an `intensity` of 1000 to match.
+#[derive(Deserialize)]
+struct ExposureDef {
+    // Exposure value at ISO 100.
+    ev: Option<f64>,
+    f_stop: Option<f64>,
+    // In seconds.
+    shutter_speed: Option<f64>,
+    #[serde(default = "default_iso")]
+    iso: f64,
+    // Extra stops of brightness on top of the above, or on their own.
+    #[serde(default)]
+    compensation: f64,
+}
+
+fn default_iso() -> f64 {
+    100.0
+}
+
+#[derive(Deserialize)]
+struct StereoDef {
+    // Eye separation in scene units.
+    #[serde(default = "default_interpupillary_distance")]
+    interpupillary_distance: f64,
+    // Distance of zero parallax; the focus distance when omitted.
+    convergence: Option<f64>,
+    #[serde(default)]
+    layout: StereoLayoutDef,
+}
+
+#[derive(Deserialize, Default)]
+enum StereoLayoutDef {
+    #[default]
+    SideBySide,
+    TopBottom,
+}
+
+fn default_interpupillary_distance() -> f64 {
+    0.064
+}
+
+fn default_sensor_width() -> f64 {
+    36.0
+}
+
+fn default_sensor_height() -> f64 {
+    24.0
+}
+
+#[derive(Deserialize, Default)]
+enum SensorFitDef {
+    #[default]
+    Auto,
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(tag = "type")]
+enum ProjectionDef {
+    #[default]
+    Perspective,
+    // Parallel rays; `view_height` is the height of the visible area in scene
+    // units and the field of view is ignored.
+    Orthographic {
+        view_height: f64,
+    },
+    Equirectangular,
+    Fisheye {
+        #[serde(default)]
+        mapping: FisheyeMappingDef,
+        // Angle covered across the image circle, in degrees.
+        #[serde(default = "default_fisheye_fov")]
+        fov: f64,
+    },
+    // Needs a 3:2 image for square faces.
+    Cubemap,
+}
+
+#[derive(Deserialize, Default)]
+enum FisheyeMappingDef {
+    #[default]
+    Equidistant,
+    Equisolid,
+}
+
+fn default_fisheye_fov() -> f64 {
+    180.0
+}
+
+#[derive(Deserialize)]
+struct LightDef {
+    // Lets objects include or exclude this light by name. The lights an
+    // environment adds are named "environment", or "sky" and "sun".
+    name: Option<String>,
+    #[serde(flatten)]
+    light: LightKindDef,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum LightKindDef {
+    Point(PointLightDef),
+    Spot(SpotLightDef),
+    Directional(DirectionalLightDef),
+    Rect(RectLightDef),
+    Disk(DiskLightDef),
+    Sphere(SphereLightDef),
+}
+
+// Point and spot lights take either `intensity` (candela, or W/sr) or
+// `power` (watts). Intensity falls off with the inverse square of distance
+// unless `legacy` is set, which keeps the old unattenuated behaviour. An
+// `ies` profile shapes the light by direction; without an explicit
+// `intensity` the profile's own peak candela is used.
+#[derive(Deserialize)]
+struct PointLightDef {
+    position: Vec3<f64>,
+    #[serde(default = "default_light_color")]
+    color: Vec3<f64>,
+    intensity: Option<f64>,
+    power: Option<f64>,
+    #[serde(default)]
+    legacy: bool,
+    // IES LM-63 file, relative to the scene file.
+    ies: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SpotLightDef {
+    position: Vec3<f64>,
+    direction: Vec3<f64>,
+    // Half-angles of the cone in degrees.
+    inner_angle: f64,
+    outer_angle: f64,
+    #[serde(default = "default_light_color")]
+    color: Vec3<f64>,
+    intensity: Option<f64>,
+    power: Option<f64>,
+    #[serde(default)]
+    legacy: bool,
+    ies: Option<String>,
+}
+
+enum LightStrength {
+    Intensity(f64),
+    Power(f64),
+}
+
+#[derive(Deserialize)]
+struct DirectionalLightDef {
+    // Direction the light travels in.
+    direction: Vec3<f64>,
+    #[serde(default = "default_light_color")]
+    color: Vec3<f64>,
+    irradiance: f64,
+    // Apparent size of the light in degrees; the sun is about 0.53.
+    #[serde(default)]
+    angular_diameter: f64,
+}
+
+// Area lights take either `radiance` (W/(sr m^2)) or total `power` (watts).
+#[derive(Deserialize)]
+struct RectLightDef {
+    corner: Vec3<f64>,
+    // Emits towards the side `edge_u x edge_v` points to.
+    edge_u: Vec3<f64>,
+    edge_v: Vec3<f64>,
+    #[serde(default = "default_light_color")]
+    color: Vec3<f64>,
+    radiance: Option<f64>,
+    power: Option<f64>,
+    #[serde(default)]
+    sampling: AreaSamplingDef,
+}
+
+#[derive(Deserialize)]
+struct DiskLightDef {
+    center: Vec3<f64>,
+    normal: Vec3<f64>,
+    radius: f64,
+    #[serde(default = "default_light_color")]
+    color: Vec3<f64>,
+    radiance: Option<f64>,
+    power: Option<f64>,
+    // Accepted like the other area lights', though disks are always
+    // sampled uniformly.
+    #[serde(default)]
+    sampling: AreaSamplingDef,
+}
+
+#[derive(Deserialize)]
+struct SphereLightDef {
+    center: Vec3<f64>,
+    radius: f64,
+    #[serde(default = "default_light_color")]
+    color: Vec3<f64>,
+    radiance: Option<f64>,
+    power: Option<f64>,
+    #[serde(default)]
+    sampling: AreaSamplingDef,
+}
+
+#[derive(Deserialize, Default)]
+enum AreaSamplingDef {
+    Uniform,
+    #[default]
+    SolidAngle,
+}
+
+fn default_light_color() -> Vec3<f64> {
+    Vec3::new(1.0, 1.0, 1.0)
+}
+
+#[derive(Deserialize)]
+struct ObjectDef {
+    #[serde(flatten)]
+    shape: ShapeDef,
+    #[serde(default = "default_true")]
+    casts_shadows: bool,
+    #[serde(default = "default_true")]
+    visible_to_camera: bool,
+    // Caught only to be rejected: no material traces reflection rays, so the
+    // flag would have nothing to act on.
+    visible_in_reflections: Option<IgnoredAny>,
+    // Names of the only lights that illuminate the object.
+    light_include: Option<Vec<String>>,
+    // Names of lights that do not illuminate the object.
+    #[serde(default)]
+    light_exclude: Vec<String>,
+    // Keyframed placement over time; the shape is used as given when empty.
+    #[serde(default)]
+    motion: Vec<KeyframeDef>,
+}
+
+#[derive(Deserialize)]
+struct KeyframeDef {
+    time: f64,
+    #[serde(default = "default_zero_vector")]
+    translation: Vec3<f64>,
+    // Euler angles about X, Y and Z in degrees, applied in that order.
+    #[serde(default = "default_zero_vector")]
+    rotation: Vec3<f64>,
+    #[serde(default = "default_scale")]
+    scale: f64,
+}
+
+fn default_zero_vector() -> Vec3<f64> {
+    Vec3::new(0.0, 0.0, 0.0)
+}
+
+fn default_scale() -> f64 {
+    1.0
 }
 
 #[derive(Deserialize)]
 #[serde(tag = "type")]
-enum ObjectDef {
+enum ShapeDef {
     Sphere(SphereDef),
     Mesh(MeshDef),
+    PointCloud(PointCloudDef),
 }
 
 #[derive(Deserialize)]
@@ -47,6 +476,8 @@ struct SphereDef {
     center: Vec3<f64>,
     radius: f64,
     material: MaterialDef,
+    // Movement of the centre per unit of time, from `center` at time zero.
+    velocity: Option<Vec3<f64>>,
 }
 
 #[derive(Deserialize)]
@@ -57,58 +488,435 @@ struct MeshDef {
     material: MaterialDef,
 }
 
+#[derive(Deserialize)]
+struct PointCloudDef {
+    // PLY file, relative to the scene file.
+    path: String,
+    #[serde(default)]
+    shape: SplatShapeDef,
+    // Used for points without a per-point radius.
+    #[serde(default = "default_splat_radius")]
+    radius: f64,
+    // Used for points without a per-point colour.
+    material: MaterialDef,
+}
+
+#[derive(Deserialize, Default)]
+enum SplatShapeDef {
+    #[default]
+    Sphere,
+    Disk,
+}
+
+fn default_splat_radius() -> f64 {
+    0.01
+}
+
 #[derive(Deserialize)]
 struct LambertianDef {
     color: RgbDef,
 }
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Deserialize, Clone, Copy, Default)]
 struct RgbDef([u8; 3]);
 
-pub fn load_scene_from_file(path: &str) -> Result<Scene, Box<dyn std::error::Error>> {
+pub fn load_scene_from_file(path: &str) -> Result<Scene, Box<dyn Error>> {
     let scene_data = std::fs::read_to_string(path)?;
     let scene_def: SceneDef = serde_json::from_str(&scene_data)?;
-    Ok(scene_def.build())
+    let base_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    scene_def.build(base_dir)
 }
 
 impl SceneDef {
-    fn build(self) -> Scene {
-        let camera = self.camera.build();
-        let hittables = self
+    fn build(self, base_dir: &Path) -> Result<Scene, Box<dyn Error>> {
+        let camera = self.camera.build(base_dir)?;
+        let mut light_names: Vec<Option<String>> =
+            self.lights.iter().map(|light| light.name.clone()).collect();
+        let mut lights: Vec<Box<dyn Light>> = self
+            .lights
+            .into_iter()
+            .map(|light_def| light_def.light.build(base_dir))
+            .collect::<Result<_, _>>()?;
+
+        let background: Arc<dyn Environment> = match self.environment {
+            Some(EnvironmentDef::Hdri(h)) => {
+                let map = Arc::new(EnvironmentMap::load(
+                    &base_dir.join(&h.path),
+                    h.rotation,
+                    h.intensity,
+                )?);
+                lights.push(Box::new(EnvironmentLight::new(Arc::clone(&map))));
+                light_names.push(Some("environment".to_string()));
+                map
+            }
+            Some(EnvironmentDef::Sky(s)) => {
+                const SKY_LIGHT_WIDTH: usize = 256;
+                const SKY_LIGHT_HEIGHT: usize = 128;
+
+                let sky = Arc::new(PreethamSky::new(
+                    s.sun_elevation,
+                    s.sun_azimuth,
+                    s.turbidity,
+                    s.intensity,
+                    s.ground_albedo,
+                ));
+                // The sky is smooth, so a coarse table is enough to sample it.
+                let baked = EnvironmentMap::bake(sky.as_ref(), SKY_LIGHT_WIDTH, SKY_LIGHT_HEIGHT);
+                lights.push(Box::new(EnvironmentLight::new(Arc::new(baked))));
+                light_names.push(Some("sky".to_string()));
+                if s.sun
+                    && let Some(sun) = sky.sun()
+                {
+                    lights.push(Box::new(sun));
+                    light_names.push(Some("sun".to_string()));
+                }
+                sky
+            }
+            None => Arc::new(SolidColor::new(self.background_color.into())),
+        };
+
+        let mut seen = HashSet::new();
+        for name in light_names.iter().flatten() {
+            if !seen.insert(name) {
+                return Err(format!("Duplicate light name '{}'", name).into());
+            }
+        }
+
+        // Built after the environment so masks cover the lights it adds.
+        let objects = self
             .objects
             .into_iter()
-            .map(|obj_def| obj_def.build())
-            .collect();
-
-        Scene::new(
-            camera,
-            self.lights,
-            hittables,
-            self.background_color.into(),
-            self.ambient_light,
-        )
+            .map(|obj_def| obj_def.build(base_dir, &light_names))
+            .collect::<Result<_, _>>()?;
+
+        let scene = Scene::new(camera, lights, objects, background, self.ambient_light);
+        Ok(match self.filter {
+            Some(filter) => scene.with_filter(filter.build()?),
+            None => scene,
+        })
     }
 }
 
 impl CameraDef {
-    fn build(self) -> Camera {
-        Camera::new(
+    fn build(self, base_dir: &Path) -> Result<Camera, Box<dyn Error>> {
+        let exposure = match &self.exposure {
+            Some(exposure) => exposure.build()?,
+            None => 1.0,
+        };
+
+        let camera = Camera::new(
             self.width,
             self.height,
             self.lookfrom,
             self.lookat,
             self.vup,
-            self.vfov,
-        )
+            self.vfov.unwrap_or(0.0),
+        );
+        let camera = match (self.vfov, self.hfov, self.focal_length) {
+            (Some(_), None, None) => camera,
+            (None, Some(hfov), None) => camera.with_hfov(hfov),
+            (None, None, Some(focal_length)) => camera.with_sensor(
+                focal_length,
+                self.sensor_width,
+                self.sensor_height,
+                match self.sensor_fit {
+                    SensorFitDef::Auto => SensorFit::Auto,
+                    SensorFitDef::Horizontal => SensorFit::Horizontal,
+                    SensorFitDef::Vertical => SensorFit::Vertical,
+                },
+            ),
+            _ => return Err("Camera needs exactly one of vfov, hfov or focal_length".into()),
+        };
+
+        let camera = camera
+            .with_lens_shift(self.shift_x, self.shift_y)
+            .with_projection(self.projection.build())
+            .with_lens(self.aperture, self.focus_distance)
+            .with_aperture_blades(self.aperture_blades, self.aperture_rotation)
+            .with_shutter(self.shutter_open, self.shutter_close)
+            .with_exposure(exposure);
+
+        let camera = match &self.realistic_lens {
+            Some(lens) => {
+                let mut system = LensSystem::load(&base_dir.join(&lens.path))?;
+                if let Some(diameter) = lens.aperture_diameter {
+                    system = system.with_aperture_diameter(diameter);
+                }
+                let focus_distance = self
+                    .focus_distance
+                    .unwrap_or((self.lookfrom - self.lookat).length());
+                camera.with_lens_system(system.focused(focus_distance)?, lens.film_diagonal * 0.001)
+            }
+            None => camera,
+        };
+
+        Ok(match self.stereo {
+            Some(stereo) => camera.with_stereo(Stereo {
+                interpupillary_distance: stereo.interpupillary_distance,
+                convergence: stereo.convergence,
+                layout: match stereo.layout {
+                    StereoLayoutDef::SideBySide => StereoLayout::SideBySide,
+                    StereoLayoutDef::TopBottom => StereoLayout::TopBottom,
+                },
+            }),
+            None => camera,
+        })
     }
 }
 
-impl ObjectDef {
-    fn build(self) -> Box<dyn Hittable> {
+impl ExposureDef {
+    fn build(&self) -> Result<f64, Box<dyn Error>> {
+        let ev100 = match (self.ev, self.f_stop, self.shutter_speed) {
+            (Some(ev), _, _) => Some(ev),
+            (None, Some(f_stop), Some(shutter_speed)) => {
+                if f_stop <= 0.0 || shutter_speed <= 0.0 || self.iso <= 0.0 {
+                    return Err("Exposure f_stop, shutter_speed and iso must be positive".into());
+                }
+                Some(exposure_value(f_stop, shutter_speed, self.iso))
+            }
+            (None, None, None) => None,
+            _ => return Err("Exposure needs both f_stop and shutter_speed, or an ev".into()),
+        };
+        let base = ev100.map_or(1.0, exposure_scale);
+        Ok(base * 2.0f64.powf(self.compensation))
+    }
+}
+
+impl ProjectionDef {
+    fn build(self) -> Projection {
+        match self {
+            ProjectionDef::Perspective => Projection::Perspective,
+            ProjectionDef::Orthographic { view_height } => Projection::Orthographic { view_height },
+            ProjectionDef::Equirectangular => Projection::Equirectangular,
+            ProjectionDef::Fisheye { mapping, fov } => Projection::Fisheye {
+                mapping: match mapping {
+                    FisheyeMappingDef::Equidistant => FisheyeMapping::Equidistant,
+                    FisheyeMappingDef::Equisolid => FisheyeMapping::Equisolid,
+                },
+                fov: fov.clamp(0.0, 360.0).to_radians(),
+            },
+            ProjectionDef::Cubemap => Projection::Cubemap,
+        }
+    }
+}
+
+impl LightKindDef {
+    fn build(self, base_dir: &Path) -> Result<Box<dyn Light>, Box<dyn Error>> {
+        Ok(match self {
+            LightKindDef::Point(p) => Box::new(p.build(base_dir)?),
+            LightKindDef::Spot(s) => Box::new(s.build(base_dir)?),
+            LightKindDef::Directional(d) => Box::new(DirectionalLight::new(
+                d.direction,
+                d.color,
+                d.irradiance,
+                d.angular_diameter,
+            )),
+            LightKindDef::Rect(r) => {
+                let area = r.edge_u.cross(&r.edge_v).length();
+                if area <= 0.0 {
+                    return Err("Rect light edges must span a non-zero area".into());
+                }
+                let radiance = area_radiance(r.radiance, r.power, area)?;
+                Box::new(RectLight::new(
+                    r.corner,
+                    r.edge_u,
+                    r.edge_v,
+                    r.color * radiance,
+                    r.sampling.build(),
+                ))
+            }
+            LightKindDef::Disk(d) => {
+                let area = PI * d.radius * d.radius;
+                let radiance = area_radiance(d.radiance, d.power, area)?;
+                Box::new(DiskLight::new(
+                    d.center,
+                    d.normal,
+                    d.radius,
+                    d.color * radiance,
+                    d.sampling.build(),
+                ))
+            }
+            LightKindDef::Sphere(s) => {
+                let area = 4.0 * PI * s.radius * s.radius;
+                let radiance = area_radiance(s.radiance, s.power, area)?;
+                Box::new(SphereLight::new(
+                    s.center,
+                    s.radius,
+                    s.color * radiance,
+                    s.sampling.build(),
+                ))
+            }
+        })
+    }
+}
+
+impl PointLightDef {
+    fn build(self, base_dir: &Path) -> Result<PointLight, Box<dyn Error>> {
+        let profile = load_profile(self.ies.as_deref(), base_dir)?;
+        let light = match light_strength(self.intensity, self.power, self.legacy, &profile)? {
+            LightStrength::Intensity(i) => {
+                PointLight::new(self.position, self.color, i, self.legacy)
+            }
+            LightStrength::Power(w) => PointLight::from_power(self.position, self.color, w),
+        };
+        Ok(match profile {
+            Some(profile) => light.with_profile(profile),
+            None => light,
+        })
+    }
+}
+
+impl SpotLightDef {
+    fn build(self, base_dir: &Path) -> Result<SpotLight, Box<dyn Error>> {
+        let profile = load_profile(self.ies.as_deref(), base_dir)?;
+        let light = match light_strength(self.intensity, self.power, self.legacy, &profile)? {
+            LightStrength::Intensity(i) => SpotLight::new(
+                self.position,
+                self.direction,
+                self.inner_angle,
+                self.outer_angle,
+                self.color,
+                i,
+                self.legacy,
+            ),
+            LightStrength::Power(w) => SpotLight::from_power(
+                self.position,
+                self.direction,
+                self.inner_angle,
+                self.outer_angle,
+                self.color,
+                w,
+            ),
+        };
+        Ok(match profile {
+            Some(profile) => light.with_profile(profile),
+            None => light,
+        })
+    }
+}
+
+fn load_profile(path: Option<&str>, base_dir: &Path) -> Result<Option<IesProfile>, Box<dyn Error>> {
+    path.map(|path| IesProfile::load(&base_dir.join(path)))
+        .transpose()
+}
+
+impl AreaSamplingDef {
+    fn build(self) -> AreaSampling {
         match self {
-            ObjectDef::Sphere(s) => Box::new(s.build()),
-            ObjectDef::Mesh(m) => Box::new(m.build()),
+            AreaSamplingDef::Uniform => AreaSampling::Uniform,
+            AreaSamplingDef::SolidAngle => AreaSampling::SolidAngle,
+        }
+    }
+}
+
+// A Lambertian emitter of area A and radiance L emits a power of L * pi * A.
+fn area_radiance(
+    radiance: Option<f64>,
+    power: Option<f64>,
+    area: f64,
+) -> Result<f64, Box<dyn Error>> {
+    match (radiance, power) {
+        (Some(l), None) => Ok(l),
+        (None, Some(w)) => Ok(w / (PI * area).max(f64::EPSILON)),
+        _ => Err("Area lights need exactly one of 'radiance' or 'power'".into()),
+    }
+}
+
+fn light_strength(
+    intensity: Option<f64>,
+    power: Option<f64>,
+    legacy: bool,
+    profile: &Option<IesProfile>,
+) -> Result<LightStrength, Box<dyn Error>> {
+    match (intensity, power) {
+        (Some(i), None) => Ok(LightStrength::Intensity(i)),
+        (None, None) if profile.is_some() => Ok(LightStrength::Intensity(
+            profile.as_ref().map_or(0.0, IesProfile::peak),
+        )),
+        (None, Some(_)) if profile.is_some() => {
+            Err("Lights with an IES profile are specified by 'intensity'".into())
         }
+        (None, Some(_)) if legacy => Err("Legacy lights are specified by 'intensity'".into()),
+        (None, Some(w)) => Ok(LightStrength::Power(w)),
+        _ => Err("Lights need exactly one of 'intensity' or 'power'".into()),
+    }
+}
+
+impl ObjectDef {
+    // `light_names` holds the name, if any, of each of the scene's lights.
+    fn build(
+        self,
+        base_dir: &Path,
+        light_names: &[Option<String>],
+    ) -> Result<SceneObject, Box<dyn Error>> {
+        if self.visible_in_reflections.is_some() {
+            let message =
+                "visible_in_reflections is not supported: no material traces reflection rays";
+            return Err(message.into());
+        }
+        let index_of = |name: &String| {
+            light_names
+                .iter()
+                .position(|n| n.as_ref() == Some(name))
+                .ok_or_else(|| format!("Unknown light '{}' in light links", name))
+        };
+
+        let mut hittable = self.shape.build(base_dir)?;
+        if !self.motion.is_empty() {
+            let keyframes = self
+                .motion
+                .iter()
+                .map(KeyframeDef::build)
+                .collect::<Result<_, _>>()?;
+            hittable = Box::new(Animated::new(hittable, keyframes));
+        }
+
+        let mut object = SceneObject::new(hittable);
+        object.casts_shadows = self.casts_shadows;
+        object.visible_to_camera = self.visible_to_camera;
+
+        if self.light_include.is_none() && self.light_exclude.is_empty() {
+            return Ok(object);
+        }
+
+        let mut mask = vec![self.light_include.is_none(); light_names.len()];
+        for name in self.light_include.iter().flatten() {
+            mask[index_of(name)?] = true;
+        }
+        for name in &self.light_exclude {
+            mask[index_of(name)?] = false;
+        }
+        Ok(object.with_light_mask(mask))
+    }
+}
+
+impl KeyframeDef {
+    fn build(&self) -> Result<Keyframe, Box<dyn Error>> {
+        // Rays are divided by the scale, and keyframes between a positive
+        // and a negative scale would pass through zero.
+        if self.scale <= 0.0 {
+            return Err(format!("Keyframe scale must be positive, got {}", self.scale).into());
+        }
+        Ok(Keyframe {
+            time: self.time,
+            translation: self.translation,
+            rotation: Vec3::new(
+                self.rotation.x.to_radians(),
+                self.rotation.y.to_radians(),
+                self.rotation.z.to_radians(),
+            ),
+            scale: self.scale,
+        })
+    }
+}
+
+impl ShapeDef {
+    fn build(self, base_dir: &Path) -> Result<Box<dyn Hittable>, Box<dyn Error>> {
+        Ok(match self {
+            ShapeDef::Sphere(s) => Box::new(s.build()),
+            ShapeDef::Mesh(m) => Box::new(m.build()),
+            ShapeDef::PointCloud(p) => Box::new(p.build(base_dir)?),
+        })
     }
 }
 
@@ -123,9 +931,42 @@ impl MeshDef {
     }
 }
 
+impl PointCloudDef {
+    fn build(self, base_dir: &Path) -> Result<PointCloud, Box<dyn Error>> {
+        let path = base_dir.join(&self.path);
+        let points = load_ply(&path)?;
+
+        let shape = match self.shape {
+            SplatShapeDef::Sphere => SplatShape::Sphere,
+            SplatShapeDef::Disk => SplatShape::Disk,
+        };
+        if shape == SplatShape::Disk && points.normals.is_none() {
+            return Err(format!(
+                "Disk splats need per-point normals (nx, ny, nz) in '{}'",
+                path.display()
+            )
+            .into());
+        }
+
+        Ok(PointCloud::new(
+            points.positions,
+            points.normals,
+            points.colors,
+            points.radii,
+            self.radius,
+            shape,
+            self.material.build(),
+        ))
+    }
+}
+
 impl SphereDef {
     fn build(self) -> Sphere {
-        Sphere::new(self.center, self.radius, self.material.build())
+        let sphere = Sphere::new(self.center, self.radius, self.material.build());
+        match self.velocity {
+            Some(velocity) => sphere.with_velocity(velocity),
+            None => sphere,
+        }
     }
 }
 
@@ -149,3 +990,115 @@ impl From<RgbDef> for image::Rgb<u8> {
         image::Rgb(rgb.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::ray::Ray;
+
+    fn build_scene(lights: &str, objects: &str) -> Result<Scene, Box<dyn Error>> {
+        let json = format!(
+            r#"{{
+                "camera": {{
+                    "width": 16, "height": 16, "vfov": 60.0,
+                    "lookfrom": {{"x": 0.0, "y": 0.0, "z": 0.0}},
+                    "lookat": {{"x": 0.0, "y": 0.0, "z": -1.0}},
+                    "vup": {{"x": 0.0, "y": 1.0, "z": 0.0}}
+                }},
+                "environment": {{"type": "Sky", "sun_elevation": 45.0}},
+                "ambient_light": {{"x": 0.0, "y": 0.0, "z": 0.0}},
+                "lights": [{}],
+                "objects": [{}]
+            }}"#,
+            lights, objects
+        );
+        serde_json::from_str::<SceneDef>(&json)?.build(Path::new(""))
+    }
+
+    fn sphere(x: f64, flags: &str) -> String {
+        format!(
+            r#"{{
+                "type": "Sphere", "center": {{"x": {}, "y": 0.0, "z": -5.0}}, "radius": 1.0,
+                "material": {{"type": "Lambertian", "color": [200, 200, 200]}}{}
+            }}"#,
+            x, flags
+        )
+    }
+
+    #[test]
+    fn test_objects_link_lights_and_control_visibility() {
+        let point = |name: &str| {
+            format!(
+                r#"{{"type": "Point", "name": "{}", "position": {{"x": 0.0, "y": 5.0, "z": 0.0}}, "intensity": 1.0}}"#,
+                name
+            )
+        };
+        let lights = format!("{}, {}", point("key"), point("fill"));
+        let objects = [
+            sphere(0.0, r#", "light_exclude": ["key"]"#),
+            sphere(3.0, r#", "light_include": ["sun"], "casts_shadows": false"#),
+            sphere(6.0, r#", "visible_to_camera": false"#),
+        ]
+        .join(", ");
+        let scene = build_scene(&lights, &objects).unwrap();
+
+        // The sky adds itself and the sun after the listed lights.
+        let lit_by = |object: &SceneObject| -> Vec<bool> {
+            (0..scene.lights.len())
+                .map(|i| object.is_lit_by(i))
+                .collect()
+        };
+        assert_eq!(lit_by(&scene.objects[0]), [false, true, true, true]);
+        assert_eq!(lit_by(&scene.objects[1]), [false, false, false, true]);
+        assert_eq!(lit_by(&scene.objects[2]), [true, true, true, true]);
+
+        let ray = |x: f64| Ray::new(Vec3::new(x, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(scene.closest_hit(&ray(3.0), 0.001, f64::INFINITY).is_some());
+        assert!(!scene.occluded(&ray(3.0), 0.001, f64::INFINITY));
+        assert!(scene.closest_hit(&ray(6.0), 0.001, f64::INFINITY).is_none());
+        assert!(scene.occluded(&ray(6.0), 0.001, f64::INFINITY));
+
+        // Names must pick out one light, including those the sky adds.
+        assert!(build_scene(&format!("{}, {}", point("key"), point("key")), "").is_err());
+        assert!(build_scene(&point("sun"), "").is_err());
+        assert!(build_scene("", &sphere(0.0, r#", "light_include": ["moon"]"#)).is_err());
+
+        // Without reflection rays, asking for this is a mistake to report.
+        let err = build_scene("", &sphere(0.0, r#", "visible_in_reflections": false"#));
+        assert!(err.is_err_and(|e| e.to_string().contains("visible_in_reflections")));
+    }
+
+    #[test]
+    fn test_camera_needs_one_field_of_view() {
+        let camera = |fov: &str| {
+            let json = format!(
+                r#"{{
+                    "width": 30, "height": 20, {}
+                    "lookfrom": {{"x": 0.0, "y": 0.0, "z": 0.0}},
+                    "lookat": {{"x": 0.0, "y": 0.0, "z": -1.0}},
+                    "vup": {{"x": 0.0, "y": 1.0, "z": 0.0}}
+                }}"#,
+                fov
+            );
+            serde_json::from_str::<CameraDef>(&json)
+                .unwrap()
+                .build(Path::new(""))
+        };
+        assert!(camera(r#""vfov": 40.0,"#).is_ok());
+        assert!(camera(r#""hfov": 60.0,"#).is_ok());
+        assert!(camera(r#""focal_length": 50.0,"#).is_ok());
+        assert!(camera("").is_err());
+        assert!(camera(r#""vfov": 40.0, "focal_length": 50.0,"#).is_err());
+        assert!(camera(r#""vfov": 40.0, "hfov": 60.0,"#).is_err());
+    }
+
+    #[test]
+    fn test_keyframes_need_a_positive_scale() {
+        let keyframe = |scale: f64| -> KeyframeDef {
+            serde_json::from_str(&format!(r#"{{"time": 0.0, "scale": {}}}"#, scale)).unwrap()
+        };
+        assert!(keyframe(2.0).build().is_ok());
+        assert!(keyframe(0.0).build().is_err());
+        assert!(keyframe(-1.0).build().is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    light::{Light, LightSample},
+    light_sampler::LightBounds,
+    math::{distribution::Distribution2D, vec3::Vec3},
+};
+use std::error::Error;
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+// Light arriving from infinitely far away, seen by rays that miss the scene.
+pub trait Environment: Send + Sync {
+    // `direction` is a unit vector pointing away from the scene.
+    fn radiance(&self, direction: Vec3<f64>) -> Vec3<f64>;
+}
+
+pub struct SolidColor {
+    color: Vec3<f64>,
+}
+
+impl SolidColor {
+    pub fn new(color: image::Rgb<u8>) -> Self {
+        Self {
+            color: Vec3::new(
+                color[0] as f64 / 255.0,
+                color[1] as f64 / 255.0,
+                color[2] as f64 / 255.0,
+            ),
+        }
+    }
+}
+
+impl Environment for SolidColor {
+    fn radiance(&self, _direction: Vec3<f64>) -> Vec3<f64> {
+        self.color
+    }
+}
+
+// An equirectangular (latitude-longitude) radiance map with +Y up. The centre
+// of the image looks down -Z.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3<f64>>,
+    intensity: f64,
+    // Rotation about +Y, in radians.
+    rotation: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    // Loads a Radiance `.hdr` or OpenEXR image.
+    pub fn load(
+        path: &Path,
+        rotation_degrees: f64,
+        intensity: f64,
+    ) -> Result<Self, Box<dyn Error>> {
+        let image = image::open(path)
+            .map_err(|e| format!("Failed to load environment map '{}': {}", path.display(), e))?
+            .into_rgb32f();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let pixels = image
+            .pixels()
+            .map(|p| Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+        Ok(Self::new(
+            width,
+            height,
+            pixels,
+            rotation_degrees,
+            intensity,
+        ))
+    }
+
+    pub fn new(
+        width: usize,
+        height: usize,
+        pixels: Vec<Vec3<f64>>,
+        rotation_degrees: f64,
+        intensity: f64,
+    ) -> Self {
+        // Rows near the poles cover less solid angle, so weight them by sin(theta).
+        let weights: Vec<f64> = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let theta = PI * ((i / width) as f64 + 0.5) / height as f64;
+                luminance(*p) * theta.sin()
+            })
+            .collect();
+        let distribution = Distribution2D::new(&weights, width, height);
+
+        Self {
+            width,
+            height,
+            pixels,
+            intensity,
+            rotation: rotation_degrees.to_radians(),
+            distribution,
+        }
+    }
+
+    // Tabulates any environment so it can be importance sampled as a light.
+    pub fn bake(environment: &dyn Environment, width: usize, height: usize) -> Self {
+        let pixels = (0..width * height)
+            .map(|i| {
+                let u = ((i % width) as f64 + 0.5) / width as f64;
+                let v = ((i / width) as f64 + 0.5) / height as f64;
+                environment.radiance(equirect_direction(u, v))
+            })
+            .collect();
+        Self::new(width, height, pixels, 0.0, 1.0)
+    }
+
+    fn direction_to_uv(&self, direction: Vec3<f64>) -> (f64, f64) {
+        equirect_uv(rotate_y(direction, -self.rotation))
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3<f64> {
+        rotate_y(equirect_direction(u, v), self.rotation)
+    }
+
+    fn lookup(&self, u: f64, v: f64) -> Vec3<f64> {
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[y * self.width + x] * self.intensity
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: Vec3<f64>) -> Vec3<f64> {
+        let (u, v) = self.direction_to_uv(direction);
+        self.lookup(u, v)
+    }
+}
+
+// Image-based lighting from an environment map, importance sampled by
+// luminance so bright regions such as the sun get most of the samples.
+pub struct EnvironmentLight {
+    map: Arc<EnvironmentMap>,
+}
+
+impl EnvironmentLight {
+    pub fn new(map: Arc<EnvironmentMap>) -> Self {
+        Self { map }
+    }
+}
+
+impl Light for EnvironmentLight {
+    fn sample(&self, _point: Vec3<f64>, u: (f64, f64)) -> Option<LightSample> {
+        let ((su, sv), pdf_uv) = self.map.distribution.sample(u);
+        let sin_theta = (sv * PI).sin();
+        if pdf_uv <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+
+        // Change of variables from the unit square to the sphere.
+        let pdf = pdf_uv / (2.0 * PI * PI * sin_theta);
+        Some(LightSample {
+            direction: self.map.uv_to_direction(su, sv),
+            distance: f64::INFINITY,
+            intensity: self.map.lookup(su, sv) / pdf,
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+}
+
+pub fn luminance(color: Vec3<f64>) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+fn equirect_uv(d: Vec3<f64>) -> (f64, f64) {
+    let u = 0.5 + d.x.atan2(-d.z) / (2.0 * PI);
+    let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+    (u.rem_euclid(1.0), v)
+}
+
+fn equirect_direction(u: f64, v: f64) -> Vec3<f64> {
+    let phi = (u - 0.5) * 2.0 * PI;
+    let theta = v * PI;
+    Vec3::new(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
+
+fn rotate_y(v: Vec3<f64>, angle: f64) -> Vec3<f64> {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(v.x * cos + v.z * sin, v.y, -v.x * sin + v.z * cos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_light_samples_match_the_map() {
+        // A dim map with one bright, coloured texel.
+        let (width, height) = (16, 8);
+        let mut pixels = vec![Vec3::new(0.1, 0.2, 0.3); width * height];
+        pixels[2 * width + 5] = Vec3::new(4.0, 2.0, 1.0);
+        let map = Arc::new(EnvironmentMap::new(width, height, pixels, 30.0, 2.0));
+        let light = EnvironmentLight::new(Arc::clone(&map));
+
+        let n = 256;
+        let mut sum = Vec3::new(0.0, 0.0, 0.0);
+        for i in 0..n {
+            for j in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let sample = light.sample(Vec3::new(0.0, 0.0, 0.0), u).unwrap();
+
+                // The texel seen along the direction, over the density the
+                // map's distribution gives that direction.
+                let (su, sv) = map.direction_to_uv(sample.direction);
+                let pdf = map.distribution.pdf(su, sv) / (2.0 * PI * PI * (sv * PI).sin());
+                let expected = map.radiance(sample.direction) / pdf;
+                assert!((sample.intensity - expected).length() < 1e-6 * expected.length());
+                sum = sum + sample.intensity;
+            }
+        }
+
+        // On average the samples add up to the radiance over the sphere.
+        let mut integral = Vec3::new(0.0, 0.0, 0.0);
+        for (i, pixel) in map.pixels.iter().enumerate() {
+            let row = (i / width) as f64;
+            let (theta0, theta1) = (PI * row / height as f64, PI * (row + 1.0) / height as f64);
+            let solid_angle = 2.0 * PI / width as f64 * (theta0.cos() - theta1.cos());
+            integral = integral + *pixel * (map.intensity * solid_angle);
+        }
+        let estimate = sum / (n * n) as f64;
+        assert!((estimate - integral).length() < 0.01 * integral.length());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::filter::Filter;
+use crate::math::vec3::Vec3;
+use image::{Rgb, RgbImage};
+
+#[derive(Clone, Copy, Default)]
+struct FilmPixel {
+    weighted_sum: Vec3<f64>,
+    weight_sum: f64,
+}
+
+// Accumulates samples through a reconstruction filter over a band of rows
+// of the image. Rendering threads each fill a band of their own, which are
+// merged in a fixed order so the result does not depend on scheduling.
+pub struct Film {
+    filter: Filter,
+    width: u32,
+    height: u32,
+    first_row: u32,
+    rows: u32,
+    pixels: Vec<FilmPixel>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32, filter: Filter) -> Self {
+        Self::band(width, height, 0, height, filter)
+    }
+
+    // Rows `first_row..first_row + rows` of a `width` by `height` image.
+    pub fn band(width: u32, height: u32, first_row: u32, rows: u32, filter: Filter) -> Self {
+        Self {
+            filter,
+            width,
+            height,
+            first_row,
+            rows,
+            pixels: vec![FilmPixel::default(); (width * rows) as usize],
+        }
+    }
+
+    // The rows a sample on row `y` can reach through `filter`.
+    pub fn band_for_row(width: u32, height: u32, y: u32, filter: Filter) -> Self {
+        let reach = filter.radius.ceil() as u32;
+        let first_row = y.saturating_sub(reach);
+        let last_row = (y + reach).min(height - 1);
+        Self::band(width, height, first_row, last_row - first_row + 1, filter)
+    }
+
+    // Adds a sample at `position` in pixel units, with (0, 0) the top left
+    // corner of the image, to every pixel whose centre is within reach.
+    pub fn add_sample(&mut self, position: (f64, f64), radiance: Vec3<f64>) {
+        let (x, y) = position;
+        let filter = self.filter;
+        let x_range = self.pixel_range(x, filter.radius, self.width);
+        let y_range = self.pixel_range(y, filter.radius, self.height);
+
+        for py in y_range {
+            if py < self.first_row || py >= self.first_row + self.rows {
+                continue;
+            }
+            for px in x_range.clone() {
+                let weight = filter.evaluate(px as f64 + 0.5 - x, py as f64 + 0.5 - y);
+                if weight == 0.0 {
+                    continue;
+                }
+                let pixel = &mut self.pixels[((py - self.first_row) * self.width + px) as usize];
+                pixel.weighted_sum = pixel.weighted_sum + radiance * weight;
+                pixel.weight_sum += weight;
+            }
+        }
+    }
+
+    fn pixel_range(&self, centre: f64, radius: f64, size: u32) -> std::ops::Range<u32> {
+        let first = (centre - 0.5 - radius).ceil().max(0.0) as u32;
+        let last = ((centre - 0.5 + radius).floor() + 1.0).clamp(0.0, size as f64) as u32;
+        first..last.max(first)
+    }
+
+    pub fn merge(&mut self, band: &Film) {
+        for row in 0..band.rows {
+            let y = band.first_row + row;
+            if y < self.first_row || y >= self.first_row + self.rows {
+                continue;
+            }
+            let target = ((y - self.first_row) * self.width) as usize;
+            let source = (row * band.width) as usize;
+            for x in 0..self.width as usize {
+                let (pixel, other) = (&mut self.pixels[target + x], band.pixels[source + x]);
+                pixel.weighted_sum = pixel.weighted_sum + other.weighted_sum;
+                pixel.weight_sum += other.weight_sum;
+            }
+        }
+    }
+
+    pub fn to_image(&self) -> RgbImage {
+        let mut image_buffer = RgbImage::new(self.width, self.rows);
+        for (i, pixel) in self.pixels.iter().enumerate() {
+            // Filters with negative lobes can leave a pixel with no weight.
+            let color = if pixel.weight_sum > 0.0 {
+                pixel.weighted_sum / pixel.weight_sum
+            } else {
+                Vec3::new(0.0, 0.0, 0.0)
+            };
+
+            // Gamma Correction
+            let r = color.x.max(0.0).powf(0.5);
+            let g = color.y.max(0.0).powf(0.5);
+            let b = color.z.max(0.0).powf(0.5);
+
+            let x = i as u32 % self.width;
+            let y = i as u32 / self.width;
+            image_buffer.put_pixel(
+                x,
+                y,
+                Rgb([
+                    (r.clamp(0.0, 0.999) * 256.0) as u8,
+                    (g.clamp(0.0, 0.999) * 256.0) as u8,
+                    (b.clamp(0.0, 0.999) * 256.0) as u8,
+                ]),
+            );
+        }
+        image_buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+
+    #[test]
+    fn test_box_filter_keeps_samples_in_their_pixel() {
+        let mut film = Film::new(3, 3, Filter::default());
+        film.add_sample((1.2, 1.7), Vec3::new(1.0, 1.0, 1.0));
+
+        let weights: Vec<f64> = film.pixels.iter().map(|p| p.weight_sum).collect();
+        assert_eq!(weights, [0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_bands_merge_into_whole_film() {
+        let filter = Filter::new(FilterKind::Tent, Some(1.5)).unwrap();
+        let samples = [((0.5, 0.5), 1.0), ((2.3, 1.6), 2.0), ((1.9, 3.8), 3.0)];
+
+        let mut whole = Film::new(4, 4, filter);
+        let mut merged = Film::new(4, 4, filter);
+        for (position, value) in samples {
+            let radiance = Vec3::new(value, value, value);
+            whole.add_sample(position, radiance);
+
+            let mut band = Film::band_for_row(4, 4, position.1 as u32, filter);
+            band.add_sample(position, radiance);
+            merged.merge(&band);
+        }
+
+        for (a, b) in whole.pixels.iter().zip(&merged.pixels) {
+            assert_eq!(a.weight_sum, b.weight_sum);
+            assert_eq!(a.weighted_sum, b.weighted_sum);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use clap::ValueEnum;
+use std::error::Error;
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum FilterKind {
+    // Averages the samples inside each pixel.
+    #[default]
+    Box,
+    Tent,
+    Gaussian,
+    // Mitchell-Netravali with B = C = 1/3.
+    Mitchell,
+    // Windowed sinc with as many lobes as the radius.
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn default_radius(self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+// Weights samples by their distance from a pixel's centre, in pixels. Each
+// sample reaches every pixel within `radius` of it on both axes.
+#[derive(Clone, Copy)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f64,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        let kind = FilterKind::default();
+        Self {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+}
+
+impl Filter {
+    // Half a pixel is the least radius that lets every sample reach the pixel
+    // it lands in; anything smaller throws samples away and can leave pixels
+    // with none at all.
+    pub fn new(kind: FilterKind, radius: Option<f64>) -> Result<Self, Box<dyn Error>> {
+        let radius = radius.unwrap_or(kind.default_radius());
+        if !(radius >= 0.5 && radius.is_finite()) {
+            return Err(format!(
+                "{:?} filter radius must be at least 0.5 pixels, got {}",
+                kind, radius
+            )
+            .into());
+        }
+        Ok(Self { kind, radius })
+    }
+
+    // Separable, so the weight is the product of the two 1D profiles.
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let r = self.radius;
+        let x = x.abs();
+        if x > r {
+            return 0.0;
+        }
+
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => 1.0 - x / r,
+            FilterKind::Gaussian => {
+                // Three standard deviations to the edge, shifted so the
+                // weight reaches zero there rather than being cut off.
+                let sigma = r / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                gaussian(x) - gaussian(r)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r),
+            FilterKind::Lanczos => sinc(x) * sinc(x / r),
+        }
+    }
+}
+
+// The Mitchell-Netravali cubic on [0, 2].
+fn mitchell(x: f64) -> f64 {
+    const B: f64 = 1.0 / 3.0;
+    const C: f64 = 1.0 / 3.0;
+
+    let weight = if x < 1.0 {
+        (12.0 - 9.0 * B - 6.0 * C) * x.powi(3)
+            + (-18.0 + 12.0 * B + 6.0 * C) * x * x
+            + (6.0 - 2.0 * B)
+    } else {
+        (-B - 6.0 * C) * x.powi(3)
+            + (6.0 * B + 30.0 * C) * x * x
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C)
+    };
+    weight / 6.0
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters_peak_at_centre_and_vanish_at_radius() {
+        for kind in [
+            FilterKind::Tent,
+            FilterKind::Gaussian,
+            FilterKind::Mitchell,
+            FilterKind::Lanczos,
+        ] {
+            let filter = Filter::new(kind, None).unwrap();
+            let centre = filter.evaluate(0.0, 0.0);
+            assert!(centre > 0.0);
+            assert!(filter.evaluate(0.3, 0.1) < centre, "{:?}", kind);
+            assert!(
+                filter.evaluate(filter.radius, 0.0).abs() < 1e-9,
+                "{:?}",
+                kind
+            );
+            assert_eq!(filter.evaluate(filter.radius + 0.1, 0.0), 0.0);
+        }
+
+        // Mitchell dips negative past one pixel, which sharpens edges.
+        let mitchell = Filter::new(FilterKind::Mitchell, None).unwrap();
+        assert!(mitchell.evaluate(1.5, 0.0) < 0.0);
+    }
+
+    #[test]
+    fn test_radius_must_cover_the_pixel() {
+        assert!(Filter::new(FilterKind::Box, Some(0.5)).is_ok());
+        for radius in [0.4, 0.0, -1.0, f64::NAN] {
+            assert!(Filter::new(FilterKind::Box, Some(radius)).is_err());
+            assert!(Filter::new(FilterKind::Tent, Some(radius)).is_err());
+        }
+    }
+}
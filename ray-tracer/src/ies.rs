@@ -0,0 +1,228 @@
+use crate::math::vec3::Vec3;
+use std::error::Error;
+use std::path::Path;
+
+// A luminaire's candela distribution from an IES LM-63 file (type C
+// photometry). Vertical angles start at the nadir, horizontal angles turn
+// counter-clockwise around the photometric axis when looking from above.
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    // One row of vertical samples per horizontal angle, in candela.
+    candela: Vec<Vec<f64>>,
+    peak: f64,
+}
+
+impl IesProfile {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read IES file '{}': {}", path.display(), e))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut lines = text.lines();
+
+        // Skip the version line and keywords up to the TILT line.
+        let tilt = lines
+            .by_ref()
+            .map(str::trim)
+            .find(|line| line.starts_with("TILT="))
+            .ok_or("IES file is missing the TILT line")?;
+
+        let mut numbers = lines.flat_map(|line| {
+            line.split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|token| !token.is_empty())
+        });
+        let mut next = || -> Result<f64, Box<dyn Error>> {
+            let token = numbers.next().ok_or("Unexpected end of IES data")?;
+            Ok(token.parse::<f64>()?)
+        };
+
+        match &tilt["TILT=".len()..] {
+            "NONE" => {}
+            "INCLUDE" => {
+                // Lamp-to-luminaire geometry, then pairs of angles and factors.
+                next()?;
+                let pairs = next()? as usize;
+                for _ in 0..2 * pairs {
+                    next()?;
+                }
+            }
+            other => return Err(format!("Unsupported IES tilt file '{}'", other).into()),
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()? as usize;
+        let horizontal_count = next()? as usize;
+        let photometric_type = next()? as u32;
+        let _units = next()?;
+        let _width = next()?;
+        let _length = next()?;
+        let _height = next()?;
+        let ballast_factor = next()?;
+        let _photometric_factor = next()?;
+        let _input_watts = next()?;
+
+        if photometric_type != 1 {
+            return Err("Only type C IES photometry is supported".into());
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err("IES file has no candela values".into());
+        }
+
+        let vertical_angles = (0..vertical_count)
+            .map(|_| next())
+            .collect::<Result<Vec<_>, _>>()?;
+        let horizontal_angles = (0..horizontal_count)
+            .map(|_| next())
+            .collect::<Result<Vec<_>, _>>()?;
+        let candela = (0..horizontal_count)
+            .map(|_| {
+                (0..vertical_count)
+                    .map(|_| Ok(next()? * multiplier * ballast_factor))
+                    .collect::<Result<Vec<_>, Box<dyn Error>>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let peak = candela.iter().flatten().fold(0.0f64, |a, &b| a.max(b));
+
+        Ok(Self {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+            peak,
+        })
+    }
+
+    // Highest intensity in the distribution, in candela.
+    pub fn peak(&self) -> f64 {
+        self.peak
+    }
+
+    // Intensity in candela at the given angles in degrees.
+    pub fn candela(&self, vertical: f64, horizontal: f64) -> f64 {
+        let horizontal = self.fold_horizontal(horizontal);
+        let (h0, h1, ht) = match locate(&self.horizontal_angles, horizontal) {
+            Some(found) => found,
+            None => return 0.0,
+        };
+        let (v0, v1, vt) = match locate(&self.vertical_angles, vertical) {
+            Some(found) => found,
+            None => return 0.0,
+        };
+
+        let lerp = |row: &Vec<f64>| row[v0] * (1.0 - vt) + row[v1] * vt;
+        lerp(&self.candela[h0]) * (1.0 - ht) + lerp(&self.candela[h1]) * ht
+    }
+
+    // Intensity along `direction` relative to the peak, in [0, 1]. `nadir`
+    // is the photometric axis and `reference` the 0 degree horizontal axis.
+    pub fn scale(&self, direction: Vec3<f64>, nadir: Vec3<f64>, reference: Vec3<f64>) -> f64 {
+        if self.peak <= 0.0 {
+            return 0.0;
+        }
+
+        let side = reference.cross(&nadir);
+        let vertical = direction.dot(&nadir).clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = direction
+            .dot(&side)
+            .atan2(direction.dot(&reference))
+            .to_degrees();
+        self.candela(vertical, horizontal) / self.peak
+    }
+
+    // Maps an angle into the range the file covers, using the symmetry
+    // implied by its last horizontal angle.
+    fn fold_horizontal(&self, angle: f64) -> f64 {
+        let angle = angle.rem_euclid(360.0);
+        let last = *self.horizontal_angles.last().unwrap_or(&0.0);
+        if last <= 0.0 {
+            0.0
+        } else if last <= 90.0 {
+            let a = if angle > 180.0 { 360.0 - angle } else { angle };
+            if a > 90.0 { 180.0 - a } else { a }
+        } else if last <= 180.0 {
+            if angle > 180.0 { 360.0 - angle } else { angle }
+        } else {
+            angle
+        }
+    }
+}
+
+// Finds the pair of samples bracketing `x` and the interpolation weight
+// between them. Returns None outside the sampled range.
+fn locate(angles: &[f64], x: f64) -> Option<(usize, usize, f64)> {
+    const TOLERANCE: f64 = 1e-6;
+    let first = *angles.first()?;
+    let last = *angles.last()?;
+    if angles.len() == 1 {
+        return Some((0, 0, 0.0));
+    }
+    if x < first - TOLERANCE || x > last + TOLERANCE {
+        return None;
+    }
+
+    let upper = angles
+        .partition_point(|&a| a <= x)
+        .clamp(1, angles.len() - 1);
+    let lower = upper - 1;
+    let span = angles[upper] - angles[lower];
+    let t = if span > 0.0 {
+        ((x - angles[lower]) / span).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    Some((lower, upper, t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../scenes/downlight.ies");
+
+    #[test]
+    fn test_parse_fixture() {
+        let profile = IesProfile::parse(FIXTURE).unwrap();
+        assert_eq!(profile.peak(), 520.0);
+        assert_eq!(profile.candela(0.0, 0.0), 520.0);
+        assert_eq!(profile.candela(90.0, 123.0), 0.0);
+        // Halfway between the 40 and 45 degree samples.
+        assert!((profile.candela(42.5, 0.0) - 151.5).abs() < 1e-9);
+        // Only the lower hemisphere is covered.
+        assert_eq!(profile.candela(120.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_scale_follows_axis() {
+        let profile = IesProfile::parse(FIXTURE).unwrap();
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let reference = Vec3::new(1.0, 0.0, 0.0);
+        assert_eq!(profile.scale(down, down, reference), 1.0);
+        assert_eq!(
+            profile.scale(Vec3::new(0.0, 1.0, 0.0), down, reference),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_quadrant_symmetry() {
+        let text = "IESNA:LM-63-2002
+TILT=NONE
+1 -1 2.0 2 2 1 1 0 0 0
+1.0 1.0 10
+0 90
+0 90
+100 50
+10 5
+";
+        let profile = IesProfile::parse(text).unwrap();
+        assert_eq!(profile.peak(), 200.0);
+        assert_eq!(profile.candela(0.0, 270.0), 20.0);
+        assert_eq!(profile.candela(0.0, 180.0), 200.0);
+        assert_eq!(profile.candela(90.0, 45.0), 55.0);
+    }
+}
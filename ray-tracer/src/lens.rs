@@ -0,0 +1,299 @@
+use crate::math::vec3::Vec3;
+use std::error::Error;
+use std::path::Path;
+
+// Prescriptions are written in millimetres; scenes are in metres.
+const MILLIMETRES: f64 = 0.001;
+
+// One spherical surface of a lens, or the aperture stop.
+#[derive(Clone, Copy)]
+struct LensElement {
+    // Positive when the centre of curvature lies towards the film. Zero for
+    // the aperture stop.
+    curvature_radius: f64,
+    // Distance along the axis to the next surface towards the film.
+    thickness: f64,
+    // Refractive index of the medium behind the surface, towards the film.
+    ior: f64,
+    aperture_radius: f64,
+}
+
+impl LensElement {
+    fn is_stop(&self) -> bool {
+        self.curvature_radius == 0.0
+    }
+}
+
+// A stack of spherical lens elements traced exactly, in the manner of Kolb,
+// Mitchell and Hanrahan ("A Realistic Camera Model for Computer Graphics",
+// 1995). Lens space has the film at z = 0 and the scene towards -z.
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+}
+
+impl LensSystem {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read lens file '{}': {}", path.display(), e))?;
+        Self::parse(&text)
+    }
+
+    // One surface per line from the object side to the film: curvature
+    // radius, thickness, index of refraction and aperture diameter, all in
+    // millimetres. A radius of zero marks the aperture stop. Lines starting
+    // with '#' are comments.
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut elements = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(str::parse::<f64>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("Invalid lens surface '{}': {}", line, e))?;
+            let [radius, thickness, ior, aperture] = values[..] else {
+                return Err(format!("Lens surface '{}' needs four values", line).into());
+            };
+
+            elements.push(LensElement {
+                curvature_radius: radius * MILLIMETRES,
+                thickness: thickness * MILLIMETRES,
+                // Stops are usually listed with an index of zero.
+                ior: if ior == 0.0 { 1.0 } else { ior },
+                aperture_radius: aperture * MILLIMETRES / 2.0,
+            });
+        }
+
+        if elements.is_empty() {
+            return Err("Lens file has no surfaces".into());
+        }
+        Ok(Self { elements })
+    }
+
+    // Opens or closes the aperture stop, up to its size in the prescription.
+    pub fn with_aperture_diameter(mut self, diameter_millimetres: f64) -> Self {
+        for element in self.elements.iter_mut().filter(|e| e.is_stop()) {
+            element.aperture_radius = element
+                .aperture_radius
+                .min(diameter_millimetres * MILLIMETRES / 2.0);
+        }
+        self
+    }
+
+    // Moves the film so that objects `distance` in front of it are sharp,
+    // using a thick lens approximation of the system.
+    pub fn focused(mut self, distance: f64) -> Result<Self, Box<dyn Error>> {
+        let ((pz0, fz0), (pz1, _)) = self
+            .thick_lens()
+            .ok_or("Lens does not form an image; check the prescription")?;
+
+        let focal_length = fz0 - pz0;
+        let z = -distance;
+        let c = (pz1 - z - pz0) * (pz1 - z - 4.0 * focal_length - pz0);
+        if c <= 0.0 {
+            return Err(format!("Lens cannot focus as close as {}", distance).into());
+        }
+
+        let delta = 0.5 * (pz1 - z + pz0 - c.sqrt());
+        if let Some(rear) = self.elements.last_mut() {
+            rear.thickness += delta;
+        }
+        Ok(self)
+    }
+
+    // Position of the rear surface, nearest the film.
+    pub fn rear_z(&self) -> f64 {
+        -self.elements.last().map_or(0.0, |e| e.thickness)
+    }
+
+    pub fn rear_aperture_radius(&self) -> f64 {
+        self.elements.last().map_or(0.0, |e| e.aperture_radius)
+    }
+
+    // Follows a ray leaving the film out into the scene. Returns None when an
+    // element's rim or the stop blocks it.
+    pub fn trace_from_film(
+        &self,
+        origin: Vec3<f64>,
+        direction: Vec3<f64>,
+    ) -> Option<(Vec3<f64>, Vec3<f64>)> {
+        let mut o = origin;
+        let mut d = direction.normalize();
+        let mut z = 0.0;
+
+        for (i, element) in self.elements.iter().enumerate().rev() {
+            z -= element.thickness;
+            let outside_ior = if i > 0 { self.elements[i - 1].ior } else { 1.0 };
+            (o, d) = self.cross(element, z, o, d, element.ior / outside_ior)?;
+        }
+        Some((o, d))
+    }
+
+    // Follows a ray from the scene through to the film side of the lens.
+    pub fn trace_from_scene(
+        &self,
+        origin: Vec3<f64>,
+        direction: Vec3<f64>,
+    ) -> Option<(Vec3<f64>, Vec3<f64>)> {
+        let mut o = origin;
+        let mut d = direction.normalize();
+        let mut z = -self.elements.iter().map(|e| e.thickness).sum::<f64>();
+
+        for (i, element) in self.elements.iter().enumerate() {
+            let outside_ior = if i > 0 { self.elements[i - 1].ior } else { 1.0 };
+            (o, d) = self.cross(element, z, o, d, outside_ior / element.ior)?;
+            z += element.thickness;
+        }
+        Some((o, d))
+    }
+
+    // Intersects one surface at axial position `z` and refracts through it
+    // with the given ratio of refractive indices.
+    fn cross(
+        &self,
+        element: &LensElement,
+        z: f64,
+        o: Vec3<f64>,
+        d: Vec3<f64>,
+        eta: f64,
+    ) -> Option<(Vec3<f64>, Vec3<f64>)> {
+        if element.is_stop() {
+            if d.z == 0.0 {
+                return None;
+            }
+            let t = (z - o.z) / d.z;
+            let p = o + d * t;
+            if t < 0.0 || p.x * p.x + p.y * p.y > element.aperture_radius.powi(2) {
+                return None;
+            }
+            return Some((p, d));
+        }
+
+        let (t, normal) = intersect_surface(element.curvature_radius, z, o, d)?;
+        let p = o + d * t;
+        if p.x * p.x + p.y * p.y > element.aperture_radius.powi(2) {
+            return None;
+        }
+        Some((p, refract(d, normal, eta)?))
+    }
+
+    // Axial positions of the principal plane and focal point on the film side
+    // and on the scene side, found by tracing rays parallel to the axis.
+    fn thick_lens(&self) -> Option<((f64, f64), (f64, f64))> {
+        let height = 0.01
+            * self
+                .elements
+                .iter()
+                .map(|e| e.aperture_radius)
+                .fold(f64::INFINITY, f64::min);
+        let front = -self.elements.iter().map(|e| e.thickness).sum::<f64>();
+
+        let scene_origin = Vec3::new(height, 0.0, front - 1.0);
+        let to_film = self.trace_from_scene(scene_origin, Vec3::new(0.0, 0.0, 1.0))?;
+        let film_side = cardinal_points(height, to_film)?;
+
+        let film_origin = Vec3::new(height, 0.0, self.rear_z() + 1.0);
+        let to_scene = self.trace_from_film(film_origin, Vec3::new(0.0, 0.0, -1.0))?;
+        let scene_side = cardinal_points(height, to_scene)?;
+
+        Some((film_side, scene_side))
+    }
+}
+
+// Where a ray that entered parallel to the axis at `height` crosses the axis
+// (the focal point) and where its extension reaches `height` again (the
+// principal plane), as (principal, focal) z positions.
+fn cardinal_points(height: f64, (o, d): (Vec3<f64>, Vec3<f64>)) -> Option<(f64, f64)> {
+    if d.x == 0.0 {
+        return None;
+    }
+    let focal = o.z + d.z * (-o.x / d.x);
+    let principal = o.z + d.z * ((height - o.x) / d.x);
+    Some((principal, focal))
+}
+
+// Intersects a sphere of signed `radius` whose surface crosses the axis at
+// `z`. Returns the distance and a normal facing back along the ray.
+fn intersect_surface(radius: f64, z: f64, o: Vec3<f64>, d: Vec3<f64>) -> Option<(f64, Vec3<f64>)> {
+    let oc = o - Vec3::new(0.0, 0.0, z + radius);
+    let a = d.length_squared();
+    let half_b = oc.dot(&d);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    let (t0, t1) = ((-half_b - root) / a, (-half_b + root) / a);
+    // Of the two intersections, the surface is the one on the vertex side.
+    let t = if (d.z > 0.0) != (radius < 0.0) {
+        t0.min(t1)
+    } else {
+        t0.max(t1)
+    };
+    if t < 0.0 {
+        return None;
+    }
+
+    let normal = (oc + d * t).normalize();
+    let normal = if normal.dot(&d) > 0.0 {
+        normal * -1.0
+    } else {
+        normal
+    };
+    Some((t, normal))
+}
+
+// Snell refraction of unit direction `d` through a surface with `normal`
+// facing against it, where `eta` is the incident over the transmitted index.
+// None on total internal reflection.
+fn refract(d: Vec3<f64>, normal: Vec3<f64>, eta: f64) -> Option<Vec3<f64>> {
+    let cos_i = -normal.dot(&d);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i).max(0.0);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some((d * eta + normal * (eta * cos_i - cos_t)).normalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../scenes/dgauss_50mm.lens");
+
+    #[test]
+    fn test_focused_lens_images_axial_point_on_film() {
+        let lens = LensSystem::parse(FIXTURE).unwrap().focused(2.0).unwrap();
+
+        // Rays from a point two metres away through different parts of the
+        // front element all land near the centre of the film.
+        let object = Vec3::new(0.0, 0.0, -2.0);
+        let front = -lens.elements.iter().map(|e| e.thickness).sum::<f64>();
+        for offset in [0.002, 0.004, -0.003] {
+            let target = Vec3::new(offset, 0.0, front);
+            let (o, d) = lens.trace_from_scene(object, target - object).unwrap();
+            let film_x = o.x + d.x * (-o.z / d.z);
+            assert!(film_x.abs() < 2e-5, "landed at {}", film_x);
+        }
+    }
+
+    #[test]
+    fn test_stop_blocks_marginal_rays() {
+        let lens = LensSystem::parse(FIXTURE)
+            .unwrap()
+            .with_aperture_diameter(2.0)
+            .focused(10.0)
+            .unwrap();
+        let rear = Vec3::new(lens.rear_aperture_radius() * 0.9, 0.0, lens.rear_z());
+        let film = Vec3::new(0.0, 0.0, 0.0);
+        assert!(lens.trace_from_film(film, rear - film).is_none());
+        let rear = Vec3::new(0.0, 0.0, lens.rear_z());
+        assert!(lens.trace_from_film(film, rear - film).is_some());
+    }
+}
@@ -1,9 +1,18 @@
 pub mod camera;
 pub mod definitions;
+pub mod environment;
+pub mod film;
+pub mod filter;
 pub mod hittable;
+pub mod ies;
+pub mod lens;
 pub mod light;
+pub mod light_sampler;
 pub mod material;
 pub mod math;
+pub mod ply;
 pub mod renderer;
+pub mod sampler;
 pub mod scene;
 pub mod shapes;
+pub mod sky;
@@ -1,17 +1,663 @@
-use crate::math::vec3::Vec3;
-use serde::Deserialize;
+use crate::{
+    ies::IesProfile,
+    light_sampler::LightBounds,
+    math::{
+        aabb::Aabb,
+        sampling::{orthonormal_basis, sample_concentric_disk, sample_cone, sample_uniform_sphere},
+        vec3::Vec3,
+    },
+};
+use std::f64::consts::PI;
+
+// Incident light at a shading point from a single light source.
+pub struct LightSample {
+    // Unit vector from the shading point towards the light.
+    pub direction: Vec3<f64>,
+    // Distance to the light, used to bound shadow rays. Infinite for lights
+    // that are infinitely far away.
+    pub distance: f64,
+    // RGB intensity arriving along `direction`.
+    pub intensity: Vec3<f64>,
+}
+
+pub trait Light: Send + Sync {
+    // Returns None when the light does not reach `point` at all. `u` is a
+    // uniform random pair in [0, 1)^2 for lights that need to pick a point or
+    // direction on their surface.
+    fn sample(&self, point: Vec3<f64>, u: (f64, f64)) -> Option<LightSample>;
+
+    // Where the light emits from and roughly how much, for choosing between
+    // many lights. None for lights infinitely far away.
+    fn bounds(&self) -> Option<LightBounds>;
+}
+
+fn max_component(color: Vec3<f64>) -> f64 {
+    color.x.max(color.y).max(color.z)
+}
+
+// Scales intensity by the inverse square of the distance travelled. Legacy
+// lights skip this and deliver their intensity unchanged at any distance.
+fn attenuation(distance: f64, legacy: bool) -> f64 {
+    if legacy {
+        1.0
+    } else {
+        1.0 / (distance * distance).max(f64::EPSILON)
+    }
+}
 
-#[derive(Deserialize)]
 pub struct PointLight {
     pub position: Vec3<f64>,
+    pub color: Vec3<f64>,
+    // Radiant intensity in W/sr (or luminous intensity in candela).
     pub intensity: f64,
+    pub legacy: bool,
+    profile: Option<IesProfile>,
 }
 
 impl PointLight {
-    pub fn new(position: Vec3<f64>, intensity: f64) -> Self {
+    pub fn new(position: Vec3<f64>, color: Vec3<f64>, intensity: f64, legacy: bool) -> Self {
+        Self {
+            position,
+            color,
+            intensity,
+            legacy,
+            profile: None,
+        }
+    }
+
+    // Shapes the light by a photometric profile, normalised so that
+    // `intensity` is the profile's peak. The profile's axis points down -Y
+    // with its 0 degree plane towards +X.
+    pub fn with_profile(mut self, profile: IesProfile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    // A light emitting `power` watts evenly over the whole sphere.
+    pub fn from_power(position: Vec3<f64>, color: Vec3<f64>, power: f64) -> Self {
+        Self::new(position, color, power / (4.0 * PI), false)
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: Vec3<f64>, _u: (f64, f64)) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance = to_light.length();
+        let direction = to_light.normalize();
+        let shape = self.profile.as_ref().map_or(1.0, |profile| {
+            profile.scale(
+                direction * -1.0,
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+            )
+        });
+        if shape <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            intensity: self.color * (self.intensity * shape * attenuation(distance, self.legacy)),
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::omnidirectional(
+            Aabb::new(self.position, self.position),
+            4.0 * PI * self.intensity * max_component(self.color),
+        ))
+    }
+}
+
+pub struct SpotLight {
+    pub position: Vec3<f64>,
+    pub direction: Vec3<f64>,
+    pub color: Vec3<f64>,
+    // Intensity along the axis of the cone, as for `PointLight`.
+    pub intensity: f64,
+    pub legacy: bool,
+    cos_inner: f64,
+    cos_outer: f64,
+    profile: Option<IesProfile>,
+}
+
+impl SpotLight {
+    // Cone angles are half-angles in degrees measured from `direction`. Light
+    // is at full strength inside the inner cone and fades out smoothly
+    // towards the outer cone.
+    pub fn new(
+        position: Vec3<f64>,
+        direction: Vec3<f64>,
+        inner_angle_degrees: f64,
+        outer_angle_degrees: f64,
+        color: Vec3<f64>,
+        intensity: f64,
+        legacy: bool,
+    ) -> Self {
+        let outer = outer_angle_degrees.clamp(0.0, 180.0);
+        let inner = inner_angle_degrees.clamp(0.0, outer);
         Self {
             position,
+            direction: direction.normalize(),
+            color,
             intensity,
+            legacy,
+            cos_inner: inner.to_radians().cos(),
+            cos_outer: outer.to_radians().cos(),
+            profile: None,
         }
     }
+
+    // As for `PointLight::with_profile`, with the profile's axis along the
+    // spot direction. The cone still limits the light.
+    pub fn with_profile(mut self, profile: IesProfile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    // A spot emitting `power` watts in total across its cone.
+    pub fn from_power(
+        position: Vec3<f64>,
+        direction: Vec3<f64>,
+        inner_angle_degrees: f64,
+        outer_angle_degrees: f64,
+        color: Vec3<f64>,
+        power: f64,
+    ) -> Self {
+        let mut light = Self::new(
+            position,
+            direction,
+            inner_angle_degrees,
+            outer_angle_degrees,
+            color,
+            0.0,
+            false,
+        );
+        // Solid angle of the cone, treating the falloff region as half lit.
+        let solid_angle = 2.0 * PI * (1.0 - 0.5 * (light.cos_inner + light.cos_outer));
+        light.intensity = power / solid_angle.max(f64::EPSILON);
+        light
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: Vec3<f64>, _u: (f64, f64)) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let direction = to_light.normalize();
+        let mut falloff = self.falloff(-direction.dot(&self.direction));
+        if let Some(profile) = &self.profile {
+            let (reference, _) = orthonormal_basis(self.direction);
+            falloff *= profile.scale(direction * -1.0, self.direction, reference);
+        }
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        let distance = to_light.length();
+        Some(LightSample {
+            direction,
+            distance,
+            intensity: self.color * (self.intensity * falloff * attenuation(distance, self.legacy)),
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let theta_inner = self.cos_inner.acos();
+        let theta_outer = self.cos_outer.acos();
+        Some(LightBounds {
+            bounds: Aabb::new(self.position, self.position),
+            power: 4.0 * PI * self.intensity * max_component(self.color),
+            axis: self.direction,
+            cos_theta_o: self.cos_inner,
+            cos_theta_e: (theta_outer - theta_inner).cos(),
+            two_sided: false,
+        })
+    }
+}
+
+// A light infinitely far away, such as the sun. With a non-zero angular
+// diameter the light covers a small disk of the sky and casts soft shadows.
+pub struct DirectionalLight {
+    // Direction the light travels in.
+    pub direction: Vec3<f64>,
+    pub color: Vec3<f64>,
+    // Irradiance on a surface facing the light.
+    pub irradiance: f64,
+    cos_half_angle: f64,
+}
+
+impl DirectionalLight {
+    pub fn new(
+        direction: Vec3<f64>,
+        color: Vec3<f64>,
+        irradiance: f64,
+        angular_diameter_degrees: f64,
+    ) -> Self {
+        let half_angle = (angular_diameter_degrees / 2.0).clamp(0.0, 90.0);
+        Self {
+            direction: direction.normalize(),
+            color,
+            irradiance,
+            cos_half_angle: half_angle.to_radians().cos(),
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: Vec3<f64>, u: (f64, f64)) -> Option<LightSample> {
+        let to_light = self.direction * -1.0;
+        let direction = if self.cos_half_angle < 1.0 {
+            sample_cone(to_light, self.cos_half_angle, u)
+        } else {
+            to_light
+        };
+
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            intensity: self.color * self.irradiance,
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+}
+
+// How an area light picks the point it is sampled from. Uniform sampling
+// spreads samples evenly over the surface; solid-angle sampling spreads them
+// evenly over the light as seen from the shading point, which is much less
+// noisy for lights that are large or close.
+#[derive(Clone, Copy, PartialEq)]
+pub enum AreaSampling {
+    Uniform,
+    SolidAngle,
+}
+
+// Turns a point sampled on a one-sided emitter into a light sample. `pdf_area`
+// is the density of the sample per unit area on the light.
+fn area_sample(
+    point: Vec3<f64>,
+    light_point: Vec3<f64>,
+    light_normal: Vec3<f64>,
+    radiance: Vec3<f64>,
+    pdf_area: f64,
+) -> Option<LightSample> {
+    let to_light = light_point - point;
+    let distance = to_light.length();
+    let direction = to_light.normalize();
+    let cos_light = -light_normal.dot(&direction);
+    if cos_light <= 0.0 || distance <= 0.0 {
+        return None;
+    }
+
+    Some(LightSample {
+        direction,
+        distance,
+        intensity: radiance * (cos_light / (distance * distance * pdf_area)),
+    })
+}
+
+// Bounds of a flat one-sided emitter facing `normal`.
+fn flat_bounds(bounds: Aabb, normal: Vec3<f64>, radiance: Vec3<f64>, area: f64) -> LightBounds {
+    LightBounds {
+        bounds,
+        power: PI * area * max_component(radiance),
+        axis: normal,
+        cos_theta_o: 1.0,
+        cos_theta_e: 0.0,
+        two_sided: false,
+    }
+}
+
+// A parallelogram spanned by `edge_u` and `edge_v` from `corner`. It emits
+// from the side that `edge_u x edge_v` points to. Solid-angle sampling only
+// handles rectangles, so other parallelograms are sampled uniformly.
+pub struct RectLight {
+    corner: Vec3<f64>,
+    edge_u: Vec3<f64>,
+    edge_v: Vec3<f64>,
+    normal: Vec3<f64>,
+    area: f64,
+    // Emitted radiance, in W/(sr m^2).
+    radiance: Vec3<f64>,
+    sampling: AreaSampling,
+}
+
+impl RectLight {
+    pub fn new(
+        corner: Vec3<f64>,
+        edge_u: Vec3<f64>,
+        edge_v: Vec3<f64>,
+        radiance: Vec3<f64>,
+        sampling: AreaSampling,
+    ) -> Self {
+        let cross = edge_u.cross(&edge_v);
+        let perpendicular = edge_u.dot(&edge_v).abs() <= 1e-6 * cross.length();
+        Self {
+            corner,
+            edge_u,
+            edge_v,
+            normal: cross.normalize(),
+            area: cross.length(),
+            radiance,
+            sampling: if perpendicular {
+                sampling
+            } else {
+                AreaSampling::Uniform
+            },
+        }
+    }
+
+    pub fn area(&self) -> f64 {
+        self.area
+    }
+
+    // Samples the spherical rectangle subtended at `point` (Urena et al.,
+    // "An Area-Preserving Parametrization for Spherical Rectangles", 2013).
+    fn sample_solid_angle(&self, point: Vec3<f64>, u: (f64, f64)) -> Option<LightSample> {
+        let ex_len = self.edge_u.length();
+        let ey_len = self.edge_v.length();
+        let x = self.edge_u / ex_len;
+        let y = self.edge_v / ey_len;
+        let z = x.cross(&y);
+
+        let d = self.corner - point;
+        let z0 = d.dot(&z);
+        // The light is one-sided: points behind it receive nothing.
+        if z0 >= 0.0 {
+            return None;
+        }
+
+        let x0 = d.dot(&x);
+        let y0 = d.dot(&y);
+        let x1 = x0 + ex_len;
+        let y1 = y0 + ey_len;
+
+        let v00 = Vec3::new(x0, y0, z0);
+        let v01 = Vec3::new(x0, y1, z0);
+        let v10 = Vec3::new(x1, y0, z0);
+        let v11 = Vec3::new(x1, y1, z0);
+
+        let n0 = v00.cross(&v10).normalize();
+        let n1 = v10.cross(&v11).normalize();
+        let n2 = v11.cross(&v01).normalize();
+        let n3 = v01.cross(&v00).normalize();
+
+        let g0 = (-n0.dot(&n1)).clamp(-1.0, 1.0).acos();
+        let g1 = (-n1.dot(&n2)).clamp(-1.0, 1.0).acos();
+        let g2 = (-n2.dot(&n3)).clamp(-1.0, 1.0).acos();
+        let g3 = (-n3.dot(&n0)).clamp(-1.0, 1.0).acos();
+
+        let b0 = n0.z;
+        let b1 = n2.z;
+        let k = 2.0 * PI - g2 - g3;
+        let solid_angle = g0 + g1 - k;
+        if solid_angle <= f64::EPSILON {
+            return None;
+        }
+
+        let au = u.0 * solid_angle + k;
+        let fu = (au.cos() * b0 - b1) / au.sin();
+        let cu = ((1.0 / (fu * fu + b0 * b0).sqrt()) * fu.signum()).clamp(-1.0, 1.0);
+        let xu = (-(cu * z0) / (1.0 - cu * cu).max(f64::EPSILON).sqrt()).clamp(x0, x1);
+
+        let dist = (xu * xu + z0 * z0).sqrt();
+        let h0 = y0 / (dist * dist + y0 * y0).sqrt();
+        let h1 = y1 / (dist * dist + y1 * y1).sqrt();
+        let hv = h0 + u.1 * (h1 - h0);
+        let yv = if hv * hv < 1.0 - 1e-6 {
+            (hv * dist) / (1.0 - hv * hv).sqrt()
+        } else {
+            y1
+        };
+
+        let offset = x * xu + y * yv + z * z0;
+        Some(LightSample {
+            direction: offset.normalize(),
+            distance: offset.length(),
+            intensity: self.radiance * solid_angle,
+        })
+    }
+}
+
+impl Light for RectLight {
+    fn sample(&self, point: Vec3<f64>, u: (f64, f64)) -> Option<LightSample> {
+        match self.sampling {
+            AreaSampling::SolidAngle => self.sample_solid_angle(point, u),
+            AreaSampling::Uniform => {
+                let light_point = self.corner + self.edge_u * u.0 + self.edge_v * u.1;
+                area_sample(
+                    point,
+                    light_point,
+                    self.normal,
+                    self.radiance,
+                    1.0 / self.area,
+                )
+            }
+        }
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let far = self.corner + self.edge_u + self.edge_v;
+        let bounds = Aabb::new(self.corner, self.corner)
+            .grow(self.corner + self.edge_u)
+            .grow(self.corner + self.edge_v)
+            .grow(far);
+        Some(flat_bounds(bounds, self.normal, self.radiance, self.area))
+    }
+}
+
+// A one-sided disk emitting towards `normal`. Solid-angle sampling isn't
+// implemented for disks, so they are always sampled uniformly.
+pub struct DiskLight {
+    center: Vec3<f64>,
+    normal: Vec3<f64>,
+    radius: f64,
+    radiance: Vec3<f64>,
+}
+
+impl DiskLight {
+    pub fn new(
+        center: Vec3<f64>,
+        normal: Vec3<f64>,
+        radius: f64,
+        radiance: Vec3<f64>,
+        _sampling: AreaSampling,
+    ) -> Self {
+        Self {
+            center,
+            normal: normal.normalize(),
+            radius,
+            radiance,
+        }
+    }
+
+    pub fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+}
+
+impl Light for DiskLight {
+    fn sample(&self, point: Vec3<f64>, u: (f64, f64)) -> Option<LightSample> {
+        let (dx, dy) = sample_concentric_disk(u);
+        let (tangent, bitangent) = orthonormal_basis(self.normal);
+        let light_point = self.center + (tangent * dx + bitangent * dy) * self.radius;
+        area_sample(
+            point,
+            light_point,
+            self.normal,
+            self.radiance,
+            1.0 / self.area(),
+        )
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        // The disk's box is the sphere around it flattened along the normal.
+        let extent = Vec3::new(
+            (1.0 - self.normal.x * self.normal.x).max(0.0).sqrt(),
+            (1.0 - self.normal.y * self.normal.y).max(0.0).sqrt(),
+            (1.0 - self.normal.z * self.normal.z).max(0.0).sqrt(),
+        ) * self.radius;
+        let bounds = Aabb::new(self.center - extent, self.center + extent);
+        Some(flat_bounds(bounds, self.normal, self.radiance, self.area()))
+    }
+}
+
+// A sphere emitting from its whole surface.
+pub struct SphereLight {
+    center: Vec3<f64>,
+    radius: f64,
+    radiance: Vec3<f64>,
+    sampling: AreaSampling,
+}
+
+impl SphereLight {
+    pub fn new(
+        center: Vec3<f64>,
+        radius: f64,
+        radiance: Vec3<f64>,
+        sampling: AreaSampling,
+    ) -> Self {
+        Self {
+            center,
+            radius,
+            radiance,
+            sampling,
+        }
+    }
+
+    pub fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn sample_uniform(&self, point: Vec3<f64>, u: (f64, f64)) -> Option<LightSample> {
+        let normal = sample_uniform_sphere(u);
+        let light_point = self.center + normal * self.radius;
+        area_sample(point, light_point, normal, self.radiance, 1.0 / self.area())
+    }
+}
+
+impl Light for SphereLight {
+    fn sample(&self, point: Vec3<f64>, u: (f64, f64)) -> Option<LightSample> {
+        let to_center = self.center - point;
+        let dist_sq = to_center.length_squared();
+        let radius_sq = self.radius * self.radius;
+
+        // The surface only emits outwards, so nothing reaches points inside.
+        if dist_sq <= radius_sq {
+            return None;
+        }
+        if self.sampling == AreaSampling::Uniform {
+            return self.sample_uniform(point, u);
+        }
+
+        // Sample the cone of directions that the sphere subtends.
+        let cos_max = (1.0 - radius_sq / dist_sq).max(0.0).sqrt();
+        let axis = to_center.normalize();
+        let direction = sample_cone(axis, cos_max, u);
+
+        // Distance to the near side of the sphere along the sampled direction.
+        let half_b = to_center.dot(&direction);
+        let discriminant = (half_b * half_b - (dist_sq - radius_sq)).max(0.0);
+        let distance = half_b - discriminant.sqrt();
+
+        Some(LightSample {
+            direction,
+            distance,
+            intensity: self.radiance * (2.0 * PI * (1.0 - cos_max)),
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::omnidirectional(
+            Aabb::around_sphere(self.center, self.radius),
+            PI * self.area() * max_component(self.radiance),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Irradiance on a surface at `point` facing `normal`, estimated over a
+    // grid of samples.
+    fn irradiance(light: &dyn Light, point: Vec3<f64>, normal: Vec3<f64>) -> f64 {
+        let n = 64;
+        let mut sum = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                if let Some(sample) = light.sample(point, u) {
+                    sum += sample.intensity.x * normal.dot(&sample.direction).max(0.0);
+                }
+            }
+        }
+        sum / (n * n) as f64
+    }
+
+    #[test]
+    fn test_area_light_sampling_strategies_agree() {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let point = Vec3::new(0.3, 0.0, 0.2);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        let rect = |sampling| {
+            let (u, v) = (Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0));
+            RectLight::new(Vec3::new(-1.0, 1.0, -1.0), u, v, white, sampling)
+        };
+        let uniform = irradiance(&rect(AreaSampling::Uniform), point, up);
+        let solid_angle = irradiance(&rect(AreaSampling::SolidAngle), point, up);
+        assert!((uniform - solid_angle).abs() < 0.01 * solid_angle);
+
+        // A disk of radius R at height h above the point gives pi R^2 / (h^2 + R^2).
+        let disk =
+            |sampling| DiskLight::new(Vec3::new(0.0, 2.0, 0.0), up * -1.0, 1.0, white, sampling);
+        let expected = PI / 5.0;
+        for sampling in [AreaSampling::Uniform, AreaSampling::SolidAngle] {
+            let estimate = irradiance(&disk(sampling), Vec3::new(0.0, 0.0, 0.0), up);
+            assert!((estimate - expected).abs() < 0.01 * expected);
+        }
+
+        // A sphere of radius R at distance d gives pi (R / d)^2.
+        let sphere = |sampling| SphereLight::new(Vec3::new(0.0, 3.0, 0.0), 1.0, white, sampling);
+        let expected = PI / 9.0;
+        for sampling in [AreaSampling::Uniform, AreaSampling::SolidAngle] {
+            let estimate = irradiance(&sphere(sampling), Vec3::new(0.0, 0.0, 0.0), up);
+            assert!((estimate - expected).abs() < 0.01 * expected);
+        }
+        let inside = SphereLight::new(Vec3::new(0.0, 0.0, 0.0), 1.0, white, AreaSampling::Uniform);
+        assert!(
+            inside
+                .sample(Vec3::new(0.1, 0.0, 0.0), (0.5, 0.5))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_parallelogram_lights_fall_back_to_uniform_sampling() {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let (u, v) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.5, 0.0, 1.0));
+        let light = RectLight::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            u,
+            v,
+            white,
+            AreaSampling::SolidAngle,
+        );
+        assert!(light.sampling == AreaSampling::Uniform);
+    }
 }
@@ -0,0 +1,382 @@
+use crate::{
+    light::Light,
+    math::{aabb::Aabb, vec3::Vec3},
+};
+use std::f64::consts::PI;
+
+// Spatial and directional extent of a light's emission, used to estimate how
+// much it can contribute at a shading point (Conty Estevez and Kulla,
+// "Importance Sampling of Many Lights with Adaptive Tree Splitting", 2018).
+#[derive(Clone, Copy)]
+pub struct LightBounds {
+    pub bounds: Aabb,
+    // Total emitted power, as a rough scalar.
+    pub power: f64,
+    // Central emission direction.
+    pub axis: Vec3<f64>,
+    // Cosine of the spread of normals around `axis`.
+    pub cos_theta_o: f64,
+    // Cosine of the angle beyond the normals over which light is emitted.
+    pub cos_theta_e: f64,
+    pub two_sided: bool,
+}
+
+impl LightBounds {
+    // Emission in every direction from within `bounds`.
+    pub fn omnidirectional(bounds: Aabb, power: f64) -> Self {
+        Self {
+            bounds,
+            power,
+            axis: Vec3::new(0.0, 0.0, 1.0),
+            cos_theta_o: -1.0,
+            cos_theta_e: 0.0,
+            two_sided: false,
+        }
+    }
+
+    fn union(&self, other: &LightBounds) -> Self {
+        if self.power == 0.0 {
+            return *other;
+        }
+        if other.power == 0.0 {
+            return *self;
+        }
+
+        let (axis, cos_theta_o) = union_cones(
+            (self.axis, self.cos_theta_o),
+            (other.axis, other.cos_theta_o),
+        );
+        Self {
+            bounds: self.bounds.union(&other.bounds),
+            power: self.power + other.power,
+            axis,
+            cos_theta_o,
+            cos_theta_e: self.cos_theta_e.min(other.cos_theta_e),
+            two_sided: self.two_sided || other.two_sided,
+        }
+    }
+
+    // A conservative estimate of the light reaching `point` on a surface with
+    // `normal`.
+    fn importance(&self, point: Vec3<f64>, normal: Vec3<f64>) -> f64 {
+        let center = self.bounds.centroid();
+        let half_diagonal = (self.bounds.max - self.bounds.min).length() / 2.0;
+        let dist_sq = (point - center).length_squared().max(half_diagonal);
+        let wi = (point - center).normalize();
+
+        let mut cos_theta_w = self.axis.dot(&wi);
+        if self.two_sided {
+            cos_theta_w = cos_theta_w.abs();
+        }
+        let sin_theta_w = safe_sqrt(1.0 - cos_theta_w * cos_theta_w);
+
+        // Angle the bounds subtend as seen from the point.
+        let radius_sq = half_diagonal * half_diagonal;
+        let center_dist_sq = (point - center).length_squared();
+        let cos_theta_b = if center_dist_sq <= radius_sq {
+            -1.0
+        } else {
+            safe_sqrt(1.0 - radius_sq / center_dist_sq)
+        };
+        let sin_theta_b = safe_sqrt(1.0 - cos_theta_b * cos_theta_b);
+
+        // Smallest angle between the emission cone and the point.
+        let sin_theta_o = safe_sqrt(1.0 - self.cos_theta_o * self.cos_theta_o);
+        let cos_theta_x = cos_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let sin_theta_x = sin_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let cos_theta_p = cos_sub_clamped(sin_theta_x, cos_theta_x, sin_theta_b, cos_theta_b);
+        if cos_theta_p <= self.cos_theta_e {
+            return 0.0;
+        }
+
+        let mut importance = self.power * cos_theta_p / dist_sq;
+
+        // Light arriving from below the surface contributes nothing, as
+        // Lambertian shading takes the signed cosine. `wi` points away from
+        // the light, hence the negation.
+        let cos_theta_i = -wi.dot(&normal);
+        let sin_theta_i = safe_sqrt(1.0 - cos_theta_i * cos_theta_i);
+        importance *= cos_sub_clamped(sin_theta_i, cos_theta_i, sin_theta_b, cos_theta_b);
+
+        importance.max(0.0)
+    }
+}
+
+enum Node {
+    Leaf {
+        bounds: LightBounds,
+        light: usize,
+    },
+    Interior {
+        bounds: LightBounds,
+        children: [usize; 2],
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+// Picks a light for a shading point with probability roughly proportional to
+// its contribution there. Lights without bounds, such as the sun or an
+// environment, are always candidates and are picked uniformly.
+pub struct LightSampler {
+    infinite: Vec<usize>,
+    nodes: Vec<Node>,
+}
+
+impl LightSampler {
+    pub fn new(lights: &[Box<dyn Light>]) -> Self {
+        let mut infinite = Vec::new();
+        let mut bounded = Vec::new();
+        for (index, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(bounds) if bounds.power > 0.0 => bounded.push((index, bounds)),
+                Some(_) => {}
+                None => infinite.push(index),
+            }
+        }
+
+        let mut sampler = Self {
+            infinite,
+            nodes: Vec::new(),
+        };
+        if !bounded.is_empty() {
+            sampler.build_node(&mut bounded);
+        }
+        sampler
+    }
+
+    fn build_node(&mut self, lights: &mut [(usize, LightBounds)]) -> usize {
+        let index = self.nodes.len();
+        if let [(light, bounds)] = lights {
+            self.nodes.push(Node::Leaf {
+                bounds: *bounds,
+                light: *light,
+            });
+            return index;
+        }
+
+        let centroid_bounds = lights
+            .iter()
+            .fold(Aabb::empty(), |b, (_, l)| b.grow(l.bounds.centroid()));
+        let axis = centroid_bounds.longest_axis();
+        let mid = lights.len() / 2;
+        lights.select_nth_unstable_by(mid, |(_, a), (_, b)| {
+            a.bounds
+                .centroid()
+                .axis(axis)
+                .total_cmp(&b.bounds.centroid().axis(axis))
+        });
+
+        // Reserve the slot so children land after their parent.
+        self.nodes.push(Node::Leaf {
+            bounds: lights[0].1,
+            light: lights[0].0,
+        });
+        let (left_lights, right_lights) = lights.split_at_mut(mid);
+        let left = self.build_node(left_lights);
+        let right = self.build_node(right_lights);
+        let bounds = self.nodes[left].bounds().union(self.nodes[right].bounds());
+        self.nodes[index] = Node::Interior {
+            bounds,
+            children: [left, right],
+        };
+        index
+    }
+
+    // Returns the index of the chosen light and the probability it was
+    // chosen with, or None if no light can reach the point.
+    pub fn sample(&self, point: Vec3<f64>, normal: Vec3<f64>, u: f64) -> Option<(usize, f64)> {
+        let bounded = if self.nodes.is_empty() { 0.0 } else { 1.0 };
+        let p_infinite = self.infinite.len() as f64 / (self.infinite.len() as f64 + bounded);
+
+        if u < p_infinite {
+            let i = ((u / p_infinite) * self.infinite.len() as f64) as usize;
+            let i = i.min(self.infinite.len() - 1);
+            return Some((self.infinite[i], p_infinite / self.infinite.len() as f64));
+        }
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut u = ((u - p_infinite) / (1.0 - p_infinite)).min(1.0 - f64::EPSILON);
+        let mut pmf = 1.0 - p_infinite;
+        let mut node = 0;
+
+        loop {
+            match &self.nodes[node] {
+                Node::Leaf { bounds, light } => {
+                    // A lone light is still checked so unreachable ones are skipped.
+                    if node > 0 || bounds.importance(point, normal) > 0.0 {
+                        return Some((*light, pmf));
+                    }
+                    return None;
+                }
+                Node::Interior { children, .. } => {
+                    let weights =
+                        children.map(|c| self.nodes[c].bounds().importance(point, normal));
+                    let total = weights[0] + weights[1];
+                    if total <= 0.0 {
+                        return None;
+                    }
+
+                    let p_left = weights[0] / total;
+                    if u < p_left {
+                        u = (u / p_left).min(1.0 - f64::EPSILON);
+                        pmf *= p_left;
+                        node = children[0];
+                    } else {
+                        u = ((u - p_left) / (1.0 - p_left)).min(1.0 - f64::EPSILON);
+                        pmf *= 1.0 - p_left;
+                        node = children[1];
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+// cos(max(0, a - b)) given the sines and cosines of a and b.
+fn cos_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        1.0
+    } else {
+        cos_a * cos_b + sin_a * sin_b
+    }
+}
+
+// sin(max(0, a - b)) given the sines and cosines of a and b.
+fn sin_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        0.0
+    } else {
+        sin_a * cos_b - cos_a * sin_b
+    }
+}
+
+// Smallest cone containing both cones, each given by axis and cos(half-angle).
+fn union_cones(a: (Vec3<f64>, f64), b: (Vec3<f64>, f64)) -> (Vec3<f64>, f64) {
+    let whole_sphere = (Vec3::new(0.0, 0.0, 1.0), -1.0);
+
+    let theta_a = a.1.clamp(-1.0, 1.0).acos();
+    let theta_b = b.1.clamp(-1.0, 1.0).acos();
+    let theta_d = a.0.dot(&b.0).clamp(-1.0, 1.0).acos();
+
+    if (theta_d + theta_b).min(PI) <= theta_a {
+        return a;
+    }
+    if (theta_d + theta_a).min(PI) <= theta_b {
+        return b;
+    }
+
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+    if theta_o >= PI {
+        return whole_sphere;
+    }
+
+    // Rotate a's axis towards b's so the new cone just covers both.
+    let theta_r = theta_o - theta_a;
+    let rotation_axis = a.0.cross(&b.0);
+    if rotation_axis.length_squared() == 0.0 {
+        return whole_sphere;
+    }
+    let k = rotation_axis.normalize();
+    let (sin_r, cos_r) = theta_r.sin_cos();
+    let v = a.0;
+    let axis = v * cos_r + k.cross(&v) * sin_r + k * (k.dot(&v) * (1.0 - cos_r));
+    (axis.normalize(), theta_o.cos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::{DirectionalLight, PointLight};
+
+    fn point_light(x: f64) -> Box<dyn Light> {
+        Box::new(PointLight::new(
+            Vec3::new(x, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            1.0,
+            false,
+        ))
+    }
+
+    #[test]
+    fn test_pmf_matches_selection_frequency() {
+        let lights: Vec<Box<dyn Light>> = vec![
+            point_light(-4.0),
+            point_light(0.0),
+            point_light(3.0),
+            point_light(10.0),
+            Box::new(DirectionalLight::new(
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(1.0, 1.0, 1.0),
+                1.0,
+                0.0,
+            )),
+        ];
+        let sampler = LightSampler::new(&lights);
+        let point = Vec3::new(0.0, 0.0, 0.0);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+
+        const N: usize = 100_000;
+        let mut counts = [0usize; 5];
+        let mut pmfs = [0.0; 5];
+        for i in 0..N {
+            let (index, pmf) = sampler
+                .sample(point, normal, (i as f64 + 0.5) / N as f64)
+                .unwrap();
+            counts[index] += 1;
+            pmfs[index] = pmf;
+        }
+
+        for (count, pmf) in counts.iter().zip(pmfs) {
+            assert!((*count as f64 / N as f64 - pmf).abs() < 1e-3);
+        }
+        assert!((pmfs.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        // Half the samples go to the sun, the rest favour the nearest light.
+        assert!((pmfs[4] - 0.5).abs() < 1e-9);
+        assert!(pmfs[1] > pmfs[0] && pmfs[1] > pmfs[2] && pmfs[2] > pmfs[3]);
+    }
+
+    #[test]
+    fn test_lights_below_the_surface_are_never_picked() {
+        let lights: Vec<Box<dyn Light>> = vec![
+            point_light(0.0),
+            Box::new(PointLight::new(
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(1.0, 1.0, 1.0),
+                1.0,
+                false,
+            )),
+        ];
+        let sampler = LightSampler::new(&lights);
+        let point = Vec3::new(0.0, 0.0, 0.0);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+
+        for u in [0.0, 0.25, 0.5, 0.75, 0.999] {
+            assert_eq!(sampler.sample(point, normal, u), Some((0, 1.0)));
+        }
+        assert_eq!(sampler.sample(point, normal * -1.0, 0.5), Some((1, 1.0)));
+    }
+
+    #[test]
+    fn test_union_cones_covers_both() {
+        let a = (Vec3::new(1.0, 0.0, 0.0), 0.9);
+        let b = (Vec3::new(0.0, 1.0, 0.0), 0.8);
+        let (axis, cos_theta) = union_cones(a, b);
+        for (w, cos) in [a, b] {
+            let theta = w.dot(&axis).clamp(-1.0, 1.0).acos() + cos.acos();
+            assert!(theta <= cos_theta.acos() + 1e-9);
+        }
+    }
+}
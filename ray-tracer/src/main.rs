@@ -1,5 +1,14 @@
 use clap::Parser;
-use ray_tracer::{definitions::load_scene_from_file, renderer::Renderer};
+use image::RgbImage;
+use ray_tracer::{
+    definitions::load_scene_from_file,
+    filter::{Filter, FilterKind},
+    renderer::{Progressive, Renderer},
+    sampler::SamplerKind,
+};
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -8,14 +17,94 @@ struct Args {
 
     output_path: String,
 
-    #[arg(short, long, default_value_t = 10)]
-    samples_per_side: u32,
+    // Samples per pixel, any number.
+    #[arg(short = 'n', long, conflicts_with = "samples_per_side")]
+    samples: Option<u32>,
+
+    // The older way to give the sample count, as the side of a square.
+    // Defaults to 10.
+    #[arg(short, long)]
+    samples_per_side: Option<u32>,
+
+    #[arg(long, value_enum, default_value_t = SamplerKind::default())]
+    sampler: SamplerKind,
+
+    // Renders with the same seed and settings come out identical.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    // Stops sampling a pixel once its noise falls below this, keeping the
+    // sample count as a maximum.
+    #[arg(long)]
+    noise_threshold: Option<f64>,
+
+    #[arg(long, default_value_t = 16)]
+    min_samples: u32,
+
+    // Pixel reconstruction filter, in place of the scene's.
+    #[arg(long, value_enum)]
+    filter: Option<FilterKind>,
+
+    // Filter radius in pixels; each filter has its own default.
+    #[arg(long, requires = "filter")]
+    filter_radius: Option<f64>,
+
+    // Renders in passes of 1, 2, 4... samples per pixel, writing the image so
+    // far to the output path between passes when a snapshot is due.
+    #[arg(long)]
+    progressive: bool,
+
+    #[arg(long)]
+    snapshot_every_passes: Option<u32>,
+
+    #[arg(long)]
+    snapshot_every_seconds: Option<f64>,
+
+    // Wall-clock budget in seconds. Keeps adding progressive passes while
+    // they fit, up to the sample count if one is given.
+    #[arg(long)]
+    time_limit: Option<f64>,
+
+    // Writes an image of how many samples each pixel took.
+    #[arg(long)]
+    sample_map: Option<String>,
 }
 
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
 
-    let total_samples = args.samples_per_side * args.samples_per_side;
+    // Without a sample count, a time limit alone decides when to stop.
+    const TIME_LIMITED_MAX_SAMPLES: u32 = 1 << 16;
+    let total_samples = match (args.samples, args.samples_per_side) {
+        (Some(samples), _) => samples,
+        (None, Some(side)) => side * side,
+        (None, None) if args.time_limit.is_some() => TIME_LIMITED_MAX_SAMPLES,
+        (None, None) => 100,
+    };
+
+    let filter = args
+        .filter
+        .map(|kind| Filter::new(kind, args.filter_radius));
+    let filter = match filter.transpose() {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Also rejects limits too large for a Duration.
+    let time_limit = match args.time_limit.map(Duration::try_from_secs_f64) {
+        Some(Ok(time_limit)) => Some(time_limit),
+        Some(Err(_)) => {
+            eprintln!(
+                "Error: --time-limit must be a finite, non-negative number of seconds, got {}",
+                args.time_limit.unwrap_or_default()
+            );
+            std::process::exit(1);
+        }
+        None => None,
+    };
 
     println!("Loading scene from: '{}'...", &args.scene_path);
 
@@ -28,21 +117,71 @@ fn main() -> std::io::Result<()> {
     };
 
     // --- Rendering ---
-    let renderer = Renderer::new(total_samples);
+    let mut renderer = Renderer::new(total_samples)
+        .with_sampler(args.sampler)
+        .with_seed(args.seed);
+    if let Some(filter) = filter {
+        renderer = renderer.with_filter(filter);
+    }
+    // Asking for snapshots implies rendering in passes.
+    if args.progressive
+        || args.snapshot_every_passes.is_some()
+        || args.snapshot_every_seconds.is_some()
+    {
+        renderer = renderer.with_progressive(Progressive {
+            snapshot_every_passes: args.snapshot_every_passes,
+            snapshot_every_seconds: args.snapshot_every_seconds,
+        });
+    }
+    if let Some(time_limit) = time_limit {
+        renderer = renderer.with_time_limit(time_limit);
+    }
+    if let Some(noise_threshold) = args.noise_threshold {
+        renderer = renderer.with_adaptive_sampling(noise_threshold, args.min_samples);
+    }
 
-    println!(
-        "Rendering with {}x{}={} total samples per pixel...",
-        args.samples_per_side, args.samples_per_side, total_samples
-    );
+    match args.time_limit {
+        Some(time_limit) => println!(
+            "Rendering for up to {}s, with at most {} samples per pixel...",
+            time_limit, total_samples
+        ),
+        None => println!("Rendering with {} samples per pixel...", total_samples),
+    }
 
-    let image_buffer = renderer.render(&scene);
+    let (image_buffer, sample_counts) = renderer.render_with_snapshots(&scene, |image, samples| {
+        println!(
+            "Saving snapshot at {} spp to {}...",
+            samples, &args.output_path
+        );
+        // A failed snapshot is not worth losing the render over.
+        if let Err(e) = save_replacing(image, &args.output_path) {
+            eprintln!("Failed to save snapshot: {}", e);
+        }
+    });
 
     println!("Saving image to {}...", &args.output_path);
-    image_buffer
-        .save(&args.output_path)
-        .expect("Failed to save image.");
+    save_replacing(&image_buffer, &args.output_path).expect("Failed to save image.");
+
+    if let Some(sample_map) = &args.sample_map {
+        println!("Saving sample counts to {}...", sample_map);
+        sample_counts
+            .save(sample_map)
+            .expect("Failed to save sample map.");
+    }
 
     println!("Done.");
 
     Ok(())
 }
+
+// Writes the image beside `path` first and then renames it into place, so
+// anything watching the file never sees it half written.
+fn save_replacing(image: &RgbImage, path: &str) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(path);
+    let file_name = path.file_name().ok_or("Output path has no file name")?;
+    // Keeps the extension, which decides the format.
+    let temp_path = path.with_file_name(format!(".{}", file_name.to_string_lossy()));
+    image.save(&temp_path)?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
@@ -1,11 +1,20 @@
 use crate::{
     hittable::HitRecord,
     math::{ray::Ray, vec3::Vec3},
-    scene::Scene,
+    sampler::Sampler,
+    scene::{Scene, SceneObject},
 };
 
 pub trait Material: Send + Sync {
-    fn shade(&self, hit_record: &HitRecord, scene: &Scene) -> Vec3<f64>;
+    // `ray` is the ray that produced the hit.
+    fn shade(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        object: &SceneObject,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+    ) -> Vec3<f64>;
 }
 
 pub struct Lambertian {
@@ -24,27 +33,43 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn shade(&self, hit_record: &HitRecord, scene: &Scene) -> Vec3<f64> {
+    fn shade(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        object: &SceneObject,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+    ) -> Vec3<f64> {
         const SHADOW_EPSILON: f64 = 0.001;
 
         let mut final_color = self.albedo * scene.ambient_light;
 
-        let primary_light = &scene.lights[0];
-        let light_dir = (primary_light.position - hit_record.point).normalize();
-        let dist_to_light = (primary_light.position - hit_record.point).length();
-
         let shadow_ray_origin = hit_record.point + hit_record.normal * SHADOW_EPSILON;
-        let shadow_ray = Ray::new(shadow_ray_origin, light_dir);
 
-        let in_shadow = scene
-            .hittables
-            .iter()
-            .any(|obj| obj.hit(&shadow_ray, 0.001, dist_to_light).is_some());
+        // One light per shading point, chosen by its likely contribution.
+        // Both dimensions are drawn up front so every path uses the same ones.
+        let (u_light, u_position) = (sampler.get_1d(), sampler.get_2d());
+        let Some((index, pmf)) =
+            scene
+                .light_sampler
+                .sample(hit_record.point, hit_record.normal, u_light)
+        else {
+            return final_color;
+        };
+        if !object.is_lit_by(index) {
+            return final_color;
+        }
+        let Some(sample) = scene.lights[index].sample(hit_record.point, u_position) else {
+            return final_color;
+        };
+
+        let shadow_ray = Ray::new(shadow_ray_origin, sample.direction, ray.time);
+        let in_shadow = scene.occluded(&shadow_ray, 0.001, sample.distance);
 
         if !in_shadow {
-            let diffuse_intensity = hit_record.normal.dot(&light_dir).max(0.0);
-            let light_color = Vec3::new(1.0, 1.0, 1.0) * primary_light.intensity;
-            let diffuse_contribution = self.albedo * light_color * diffuse_intensity;
+            let diffuse_intensity = hit_record.normal.dot(&sample.direction).max(0.0);
+            let diffuse_contribution = self.albedo * sample.intensity * (diffuse_intensity / pmf);
             final_color = final_color + diffuse_contribution;
         }
 
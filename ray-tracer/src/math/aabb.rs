@@ -0,0 +1,82 @@
+use crate::math::{ray::Ray, vec3::Vec3};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3<f64>,
+    pub max: Vec3<f64>,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3<f64>, max: Vec3<f64>) -> Self {
+        Self { min, max }
+    }
+
+    // An inverted box that any union or grow will replace.
+    pub fn empty() -> Self {
+        Self {
+            min: Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn around_sphere(center: Vec3<f64>, radius: f64) -> Self {
+        let extent = Vec3::new(radius, radius, radius);
+        Self::new(center - extent, center + extent)
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self::new(
+            Vec3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            Vec3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        )
+    }
+
+    pub fn grow(&self, point: Vec3<f64>) -> Self {
+        self.union(&Aabb::new(point, point))
+    }
+
+    pub fn centroid(&self) -> Vec3<f64> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Slab test; the interval is narrowed per axis and rejected once it empties.
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction.axis(axis);
+            let mut near = (self.min.axis(axis) - ray.origin.axis(axis)) * inv_d;
+            let mut far = (self.max.axis(axis) - ray.origin.axis(axis)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+            if t1 < t0 {
+                return false;
+            }
+        }
+        true
+    }
+}
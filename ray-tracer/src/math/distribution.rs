@@ -0,0 +1,149 @@
+// Piecewise-constant distributions for importance sampling tabulated
+// functions such as environment map luminance.
+
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    // `func` holds non-negative values over equal steps of [0, 1]. An all-zero
+    // function is sampled uniformly.
+    pub fn new(func: Vec<f64>) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].abs() / n as f64;
+        }
+
+        let integral = cdf[n];
+        if integral > 0.0 {
+            for value in cdf.iter_mut().skip(1) {
+                *value /= integral;
+            }
+        } else {
+            for (i, value) in cdf.iter_mut().enumerate() {
+                *value = i as f64 / n as f64;
+            }
+        }
+
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.func.is_empty()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    // Returns a position in [0, 1), its density and the index of the segment
+    // it fell in.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        // Last cdf entry that is <= u.
+        let offset = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .saturating_sub(1)
+            .min(self.len() - 1);
+
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        let x = ((offset as f64 + du) / self.len() as f64).min(1.0 - f64::EPSILON);
+        (x, self.pdf_at(offset), offset)
+    }
+
+    fn pdf_at(&self, offset: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[offset].abs() / self.integral
+        } else {
+            1.0
+        }
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        let offset = ((x * self.len() as f64) as usize).min(self.len() - 1);
+        self.pdf_at(offset)
+    }
+}
+
+// A distribution over [0, 1]^2 built from a row-major table: rows are picked
+// by their total, then a column within the row.
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+        let conditional: Vec<Distribution1D> = func
+            .chunks_exact(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral()).collect());
+        Self {
+            conditional,
+            marginal,
+        }
+    }
+
+    // Returns a point (u, v), where v selects the row, and its density.
+    pub fn sample(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample(u.1);
+        let (x, pdf_u, _) = self.conditional[row].sample(u.0);
+        ((x, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.conditional.len() as f64) as usize).min(self.conditional.len() - 1);
+        self.marginal.pdf(v) * self.conditional[row].pdf(u)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_follows_function() {
+        let dist = Distribution1D::new(vec![1.0, 3.0]);
+        let (x, pdf, offset) = dist.sample(0.5);
+        assert_eq!(offset, 1);
+        assert!(x > 0.5 && x < 1.0);
+        assert!((pdf - 1.5).abs() < 1e-12);
+        assert!((dist.pdf(0.1) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_zero_function_is_uniform() {
+        let dist = Distribution1D::new(vec![0.0; 4]);
+        let (x, pdf, offset) = dist.sample(0.6);
+        assert_eq!(offset, 2);
+        assert!((x - 0.6).abs() < 1e-12);
+        assert_eq!(pdf, 1.0);
+    }
+
+    #[test]
+    fn test_2d_pdf_matches_sample() {
+        let func = [0.0, 1.0, 2.0, 5.0, 1.0, 1.0];
+        let dist = Distribution2D::new(&func, 3, 2);
+        let ((u, v), pdf) = dist.sample((0.3, 0.8));
+        assert!((dist.pdf(u, v) - pdf).abs() < 1e-12);
+        assert!(v >= 0.5);
+    }
+}
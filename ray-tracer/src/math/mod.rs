@@ -1,2 +1,5 @@
+pub mod aabb;
+pub mod distribution;
 pub mod ray;
+pub mod sampling;
 pub mod vec3;
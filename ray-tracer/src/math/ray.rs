@@ -4,11 +4,17 @@ use crate::math::vec3::Vec3;
 pub struct Ray {
     pub origin: Vec3<f64>,
     pub direction: Vec3<f64>,
+    // Moment within the shutter interval the ray samples, for motion blur.
+    pub time: f64,
 }
 
 impl Ray {
-    pub fn new(origin: Vec3<f64>, direction: Vec3<f64>) -> Self {
-        Self { origin, direction }
+    pub fn new(origin: Vec3<f64>, direction: Vec3<f64>, time: f64) -> Self {
+        Self {
+            origin,
+            direction,
+            time,
+        }
     }
 
     pub fn at(&self, t: f64) -> Vec3<f64> {
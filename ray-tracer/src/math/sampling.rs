@@ -0,0 +1,68 @@
+use crate::math::vec3::Vec3;
+use std::f64::consts::PI;
+
+// Builds two unit vectors that together with `n` form an orthonormal basis.
+pub fn orthonormal_basis(n: Vec3<f64>) -> (Vec3<f64>, Vec3<f64>) {
+    let helper = if n.x.abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let tangent = helper.cross(&n).normalize();
+    let bitangent = n.cross(&tangent);
+    (tangent, bitangent)
+}
+
+// Uniformly samples a direction inside the cone around `axis` whose half-angle
+// has cosine `cos_max`.
+pub fn sample_cone(axis: Vec3<f64>, cos_max: f64, u: (f64, f64)) -> Vec3<f64> {
+    let cos_theta = 1.0 - u.0 * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+
+    let (tangent, bitangent) = orthonormal_basis(axis);
+    (tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + axis * cos_theta)
+        .normalize()
+}
+
+// Maps a uniform square sample onto the unit disk with Shirley's concentric
+// mapping, which keeps strata intact.
+pub fn sample_concentric_disk(u: (f64, f64)) -> (f64, f64) {
+    let ox = 2.0 * u.0 - 1.0;
+    let oy = 2.0 * u.1 - 1.0;
+    if ox == 0.0 && oy == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (r, theta) = if ox.abs() > oy.abs() {
+        (ox, (PI / 4.0) * (oy / ox))
+    } else {
+        (oy, PI / 2.0 - (PI / 4.0) * (ox / oy))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+pub fn sample_uniform_sphere(u: (f64, f64)) -> Vec3<f64> {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Uniformly samples a regular polygon with `sides` corners inscribed in the
+// unit circle, turned by `rotation` radians.
+pub fn sample_regular_polygon(sides: u32, rotation: f64, u: (f64, f64)) -> (f64, f64) {
+    // Pick one of the triangles fanning out from the centre, then reuse the
+    // leftover fraction of `u.0` to place the point inside it.
+    let scaled = u.0 * sides as f64;
+    let index = (scaled as u32).min(sides - 1);
+    let u0 = scaled - index as f64;
+
+    let step = 2.0 * PI / sides as f64;
+    let a0 = rotation + index as f64 * step;
+    let a1 = a0 + step;
+
+    let su = u0.sqrt();
+    let (b0, b1) = (su * (1.0 - u.1), su * u.1);
+    (b0 * a0.cos() + b1 * a1.cos(), b0 * a0.sin() + b1 * a1.sin())
+}
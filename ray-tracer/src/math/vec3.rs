@@ -2,7 +2,7 @@ use num_traits::{Float, Zero};
 use serde::Deserialize;
 use std::ops::{Add, Div, Mul, Sub};
 
-#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
 pub struct Vec3<T> {
     pub x: T,
     pub y: T,
@@ -15,6 +15,17 @@ impl<T> Vec3<T> {
     }
 }
 
+impl<T: Copy> Vec3<T> {
+    pub fn axis(&self, index: usize) -> T {
+        match index {
+            0 => self.x,
+            1 => self.y,
+            2 => self.z,
+            _ => panic!("Vec3 axis index out of range: {}", index),
+        }
+    }
+}
+
 impl<T> Vec3<T>
 where
     T: Float,
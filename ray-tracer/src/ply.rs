@@ -0,0 +1,383 @@
+use crate::math::vec3::Vec3;
+use image::Rgb;
+use std::error::Error;
+use std::path::Path;
+
+// Per-vertex data read from a PLY file. Only positions are mandatory; the
+// optional attributes are present when every vertex carries them.
+pub struct PlyPoints {
+    pub positions: Vec<Vec3<f64>>,
+    pub normals: Option<Vec<Vec3<f64>>>,
+    pub colors: Option<Vec<Rgb<u8>>>,
+    pub radii: Option<Vec<f64>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+enum Property {
+    Scalar {
+        name: String,
+        ty: ScalarType,
+    },
+    List {
+        count_ty: ScalarType,
+        item_ty: ScalarType,
+    },
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+pub fn load_ply(path: &Path) -> Result<PlyPoints, Box<dyn Error>> {
+    let data = std::fs::read(path)
+        .map_err(|e| format!("Failed to read PLY file '{}': {}", path.display(), e))?;
+    parse_ply(&data)
+}
+
+pub fn parse_ply(data: &[u8]) -> Result<PlyPoints, Box<dyn Error>> {
+    let (format, elements, body_start) = parse_header(data)?;
+    let mut body = match format {
+        Format::Ascii => {
+            Body::Ascii(std::str::from_utf8(&data[body_start..])?.split_ascii_whitespace())
+        }
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => Body::Binary {
+            bytes: &data[body_start..],
+            pos: 0,
+            big_endian: format == Format::BinaryBigEndian,
+        },
+    };
+
+    let mut points = None;
+    for element in &elements {
+        if element.name == "vertex" {
+            points = Some(read_vertices(element, &mut body)?);
+        } else {
+            skip_element(element, &mut body)?;
+        }
+    }
+
+    points.ok_or_else(|| "PLY file has no 'vertex' element".into())
+}
+
+fn parse_header(data: &[u8]) -> Result<(Format, Vec<Element>, usize), Box<dyn Error>> {
+    const END_HEADER: &[u8] = b"end_header";
+
+    let end = data
+        .windows(END_HEADER.len())
+        .position(|w| w == END_HEADER)
+        .ok_or("PLY header is missing 'end_header'")?;
+    let body_start = data[end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map(|i| end + i + 1)
+        .unwrap_or(data.len());
+
+    let header = std::str::from_utf8(&data[..end])?;
+    let mut lines = header.lines().map(str::trim);
+
+    if lines.next() != Some("ply") {
+        return Err("Not a PLY file (missing 'ply' magic)".into());
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", kind, _version] => {
+                format = Some(match *kind {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    other => return Err(format!("Unknown PLY format '{}'", other).into()),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse()?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_ty, item_ty, _name] => elements
+                .last_mut()
+                .ok_or("PLY property declared before any element")?
+                .properties
+                .push(Property::List {
+                    count_ty: ScalarType::parse(count_ty)?,
+                    item_ty: ScalarType::parse(item_ty)?,
+                }),
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or("PLY property declared before any element")?
+                .properties
+                .push(Property::Scalar {
+                    name: name.to_string(),
+                    ty: ScalarType::parse(ty)?,
+                }),
+            _ => return Err(format!("Malformed PLY header line: '{}'", line).into()),
+        }
+    }
+
+    let format = format.ok_or("PLY header is missing the 'format' line")?;
+    Ok((format, elements, body_start))
+}
+
+fn read_vertices(element: &Element, body: &mut Body) -> Result<PlyPoints, Box<dyn Error>> {
+    let find = |wanted: &[&str]| {
+        element.properties.iter().position(|p| match p {
+            Property::Scalar { name, .. } => wanted.contains(&name.as_str()),
+            Property::List { .. } => false,
+        })
+    };
+    let find_all = |names: [&[&str]; 3]| match (find(names[0]), find(names[1]), find(names[2])) {
+        (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+        _ => None,
+    };
+
+    let position = find_all([&["x"], &["y"], &["z"]]).ok_or("PLY vertices need x, y and z")?;
+    let normal = find_all([&["nx"], &["ny"], &["nz"]]);
+    let color = find_all([
+        &["red", "r", "diffuse_red"],
+        &["green", "g", "diffuse_green"],
+        &["blue", "b", "diffuse_blue"],
+    ]);
+    let radius = find(&["radius"]);
+
+    // Float colours are stored in [0, 1], integer ones in [0, 255].
+    let color_scale = match color.map(|[r, ..]| &element.properties[r]) {
+        Some(Property::Scalar {
+            ty: ScalarType::F32 | ScalarType::F64,
+            ..
+        }) => 255.0,
+        _ => 1.0,
+    };
+
+    // Left to grow rather than sized by the header, whose count may be
+    // far more than the file holds.
+    let mut points = PlyPoints {
+        positions: Vec::new(),
+        normals: normal.map(|_| Vec::new()),
+        colors: color.map(|_| Vec::new()),
+        radii: radius.map(|_| Vec::new()),
+    };
+
+    let mut row = vec![0.0; element.properties.len()];
+    for _ in 0..element.count {
+        for (value, property) in row.iter_mut().zip(&element.properties) {
+            match property {
+                Property::Scalar { ty, .. } => *value = body.read(*ty)?,
+                Property::List { count_ty, item_ty } => {
+                    let count = body.read(*count_ty)? as usize;
+                    for _ in 0..count {
+                        body.read(*item_ty)?;
+                    }
+                }
+            }
+        }
+
+        let vec3_at = |[a, b, c]: [usize; 3]| Vec3::new(row[a], row[b], row[c]);
+        points.positions.push(vec3_at(position));
+        if let (Some(normals), Some(idx)) = (points.normals.as_mut(), normal) {
+            normals.push(vec3_at(idx).normalize());
+        }
+        if let (Some(colors), Some(idx)) = (points.colors.as_mut(), color) {
+            let channel = |i: usize| (row[idx[i]] * color_scale).round().clamp(0.0, 255.0) as u8;
+            colors.push(Rgb([channel(0), channel(1), channel(2)]));
+        }
+        if let (Some(radii), Some(idx)) = (points.radii.as_mut(), radius) {
+            radii.push(row[idx]);
+        }
+    }
+
+    Ok(points)
+}
+
+fn skip_element(element: &Element, body: &mut Body) -> Result<(), Box<dyn Error>> {
+    for _ in 0..element.count {
+        for property in &element.properties {
+            match property {
+                Property::Scalar { ty, .. } => {
+                    body.read(*ty)?;
+                }
+                Property::List { count_ty, item_ty } => {
+                    let count = body.read(*count_ty)? as usize;
+                    for _ in 0..count {
+                        body.read(*item_ty)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            other => return Err(format!("Unknown PLY property type '{}'", other).into()),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+}
+
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary {
+        bytes: &'a [u8],
+        pos: usize,
+        big_endian: bool,
+    },
+}
+
+impl Body<'_> {
+    fn read(&mut self, ty: ScalarType) -> Result<f64, Box<dyn Error>> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or("Unexpected end of PLY data")?;
+                Ok(token.parse::<f64>()?)
+            }
+            Body::Binary {
+                bytes,
+                pos,
+                big_endian,
+            } => {
+                let size = ty.size();
+                let raw = bytes
+                    .get(*pos..*pos + size)
+                    .ok_or("Unexpected end of PLY data")?;
+                *pos += size;
+
+                let mut buf = [0u8; 8];
+                buf[..size].copy_from_slice(raw);
+                if *big_endian {
+                    buf[..size].reverse();
+                }
+
+                Ok(match ty {
+                    ScalarType::I8 => buf[0] as i8 as f64,
+                    ScalarType::U8 => buf[0] as f64,
+                    ScalarType::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+                    ScalarType::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+                    ScalarType::I32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    ScalarType::U32 => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    ScalarType::F32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    ScalarType::F64 => f64::from_le_bytes(buf),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_points() {
+        let data = b"ply
+format ascii 1.0
+comment two coloured points
+element vertex 2
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 1 2 255 0 0
+3 4 5 0 128 255
+3 0 1 0
+";
+        let points = parse_ply(data).unwrap();
+        assert_eq!(
+            points.positions,
+            vec![Vec3::new(0.0, 1.0, 2.0), Vec3::new(3.0, 4.0, 5.0)]
+        );
+        assert_eq!(points.colors.unwrap()[1], Rgb([0, 128, 255]));
+        assert!(points.normals.is_none());
+        assert!(points.radii.is_none());
+    }
+
+    #[test]
+    fn test_binary_points() {
+        let mut data = b"ply
+format binary_big_endian 1.0
+element vertex 1
+property double x
+property double y
+property double z
+property float nx
+property float ny
+property float nz
+property float radius
+end_header
+"
+        .to_vec();
+        for v in [1.0f64, -2.0, 0.5] {
+            data.extend_from_slice(&v.to_be_bytes());
+        }
+        for v in [0.0f32, 2.0, 0.0, 0.25] {
+            data.extend_from_slice(&v.to_be_bytes());
+        }
+
+        let points = parse_ply(&data).unwrap();
+        assert_eq!(points.positions, vec![Vec3::new(1.0, -2.0, 0.5)]);
+        assert_eq!(points.normals.unwrap(), vec![Vec3::new(0.0, 1.0, 0.0)]);
+        assert_eq!(points.radii.unwrap(), vec![0.25]);
+    }
+
+    #[test]
+    fn test_truncated_binary() {
+        let mut data = b"ply
+format binary_little_endian 1.0
+element vertex 2
+property float x
+property float y
+property float z
+end_header
+"
+        .to_vec();
+        data.extend_from_slice(&[0u8; 12]);
+        assert!(parse_ply(&data).is_err());
+    }
+}
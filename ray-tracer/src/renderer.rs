@@ -1,104 +1,435 @@
+use crate::camera::CameraSample;
+use crate::environment::luminance;
+use crate::film::Film;
+use crate::filter::Filter;
 use crate::math::vec3::Vec3;
+use crate::sampler::{Sampler, SamplerKind};
 use crate::scene::Scene;
-use image::{Rgb, RgbImage};
+use image::{GrayImage, Luma, RgbImage};
 use indicatif::{ProgressBar, ProgressStyle};
-use rand::Rng;
 use rayon::prelude::*;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+// Rows rendered at once. Each row's film band spans the filter's reach, so
+// this bounds the memory a pass holds, while leaving enough rows to keep
+// every thread busy.
+const ROWS_PER_BLOCK: usize = 64;
+
+// Stops sampling a pixel once its estimated error is small enough, with
+// `samples_per_pixel` as the most any pixel gets.
+#[derive(Clone, Copy)]
+pub struct AdaptiveSampling {
+    // Standard error allowed in the displayed pixel value, on a 0 to 1 scale.
+    pub noise_threshold: f64,
+    // Samples every pixel takes before its error is trusted.
+    pub min_samples: u32,
+}
+
+// Renders the whole frame in passes of 1, 2, 4... samples per pixel, so the
+// image can be looked at long before it is finished. Passes stop growing
+// once they would take longer than `snapshot_every_seconds`.
+#[derive(Clone, Copy, Default)]
+pub struct Progressive {
+    pub snapshot_every_passes: Option<u32>,
+    pub snapshot_every_seconds: Option<f64>,
+}
+
+impl Progressive {
+    fn snapshot_due(&self, passes_done: u32, since_last: Duration) -> bool {
+        self.snapshot_every_passes
+            .is_some_and(|n| passes_done.is_multiple_of(n.max(1)))
+            || self
+                .snapshot_every_seconds
+                .is_some_and(|seconds| since_last.as_secs_f64() >= seconds)
+    }
+}
 
 pub struct Renderer {
     pub samples_per_pixel: u32,
+    pub sampler: SamplerKind,
+    pub seed: u64,
+    pub adaptive: Option<AdaptiveSampling>,
+    // Overrides the scene's filter when set.
+    pub filter: Option<Filter>,
+    pub progressive: Option<Progressive>,
+    // Stops a progressive render after the last pass that fits.
+    pub time_limit: Option<Duration>,
 }
 
 impl Renderer {
     pub fn new(samples_per_pixel: u32) -> Self {
-        Self { samples_per_pixel }
+        Self {
+            samples_per_pixel,
+            sampler: SamplerKind::default(),
+            seed: 0,
+            adaptive: None,
+            filter: None,
+            progressive: None,
+            time_limit: None,
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_sampler(mut self, sampler: SamplerKind) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn with_progressive(mut self, progressive: Progressive) -> Self {
+        self.progressive = Some(progressive);
+        self
+    }
+
+    // Renders progressively, keeping `samples_per_pixel` as the most any
+    // pixel can get.
+    pub fn with_time_limit(mut self, time_limit: Duration) -> Self {
+        self.progressive.get_or_insert_default();
+        self.time_limit = Some(time_limit);
+        self
+    }
+
+    pub fn with_adaptive_sampling(mut self, noise_threshold: f64, min_samples: u32) -> Self {
+        self.adaptive = Some(AdaptiveSampling {
+            noise_threshold,
+            min_samples: min_samples.max(2),
+        });
+        self
     }
 
     pub fn render(&self, scene: &Scene) -> RgbImage {
-        let mut image_buffer = RgbImage::new(scene.camera.width, scene.camera.height);
-        let total_pixels = (scene.camera.width * scene.camera.height) as u64;
-
-        let pb = ProgressBar::new(total_pixels);
-        pb.set_style(ProgressStyle::default_bar()
-            .template("{spinner:.green} Rendering: [{bar:40.cyan/blue}] {percent}% | {pos}/{len}px ({eta})")
-            .expect("Failed to create progress bar template")
-            .progress_chars("#>-"));
-
-        let pixels: Vec<(u32, u32)> = (0..scene.camera.height)
-            .flat_map(|y| (0..scene.camera.width).map(move |x| (x, y)))
-            .collect();
-
-        let rendered_pixels: Vec<Rgb<u8>> = pixels
-            .into_par_iter()
-            .map(|(px, py)| {
-                pb.inc(1);
-
-                let mut rng = rand::thread_rng();
-                let mut pixel_color = Vec3::new(0.0, 0.0, 0.0);
-
-                let samples_sqrt = (self.samples_per_pixel as f64).sqrt() as u32;
-                let samples_per_side = if samples_sqrt == 0 { 1 } else { samples_sqrt };
-
-                for i in 0..samples_per_side {
-                    for j in 0..samples_per_side {
-                        let u = (px as f64
-                            + (i as f64 + rng.r#gen::<f64>()) / samples_per_side as f64)
-                            / (scene.camera.width - 1) as f64;
-
-                        let v = (py as f64
-                            + (j as f64 + rng.r#gen::<f64>()) / samples_per_side as f64)
-                            / (scene.camera.height - 1) as f64;
-
-                        let ray = scene.camera.get_ray(u, 1.0 - v);
-                        pixel_color = pixel_color + self.trace_ray(&ray, scene);
-                    }
-                }
-
-                let total_samples = (samples_per_side * samples_per_side) as f64;
-                pixel_color = pixel_color / total_samples;
-
-                // Gamma Correction
-                let r = pixel_color.x.powf(0.5);
-                let g = pixel_color.y.powf(0.5);
-                let b = pixel_color.z.powf(0.5);
-
-                Rgb([
-                    (r.clamp(0.0, 0.999) * 256.0) as u8,
-                    (g.clamp(0.0, 0.999) * 256.0) as u8,
-                    (b.clamp(0.0, 0.999) * 256.0) as u8,
-                ])
-            })
-            .collect();
-
-        pb.finish_with_message("Parallel rendering complete. Writing to image...");
-
-        for (i, pixel) in rendered_pixels.into_iter().enumerate() {
-            let x = i as u32 % scene.camera.width;
-            let y = i as u32 / scene.camera.width;
-            image_buffer.put_pixel(x, y, pixel);
+        self.render_with_sample_counts(scene).0
+    }
+
+    // Also returns how many samples each pixel took, scaled so that the
+    // most allowed is white.
+    pub fn render_with_sample_counts(&self, scene: &Scene) -> (RgbImage, GrayImage) {
+        self.render_with_snapshots(scene, |_, _| {})
+    }
+
+    // When rendering progressively, hands `snapshot` the image so far and
+    // its samples per pixel whenever one is due.
+    pub fn render_with_snapshots(
+        &self,
+        scene: &Scene,
+        mut snapshot: impl FnMut(&RgbImage, u32),
+    ) -> (RgbImage, GrayImage) {
+        let (width, height) = (scene.camera.width, scene.camera.height);
+        let filter = self.filter.or(scene.filter).unwrap_or_default();
+        let samples_per_pixel = self.samples_per_pixel.max(1);
+
+        let mut film = Film::new(width, height, filter);
+        let mut statistics = vec![PixelStatistics::default(); (width * height) as usize];
+
+        let mut samples = match self.progressive {
+            Some(_) => 0..1,
+            None => 0..samples_per_pixel,
+        };
+        let started = Instant::now();
+        let mut last_snapshot = started;
+
+        for pass in 1.. {
+            let pass_started = Instant::now();
+            let pb = ProgressBar::new((width * height) as u64);
+            pb.set_style(ProgressStyle::default_bar()
+                .template("{spinner:.green} Rendering{msg}: [{bar:40.cyan/blue}] {percent}% | {pos}/{len}px ({eta})")
+                .expect("Failed to create progress bar template")
+                .progress_chars("#>-"));
+            if self.progressive.is_some() {
+                pb.set_message(format!(" pass {} ({} spp)", pass, samples.end));
+            }
+
+            self.render_pass(
+                scene,
+                filter,
+                &mut film,
+                samples.clone(),
+                &mut statistics,
+                &pb,
+            );
+            pb.finish();
+
+            let Some(next) = self.next_pass(&samples, started.elapsed(), pass_started.elapsed())
+            else {
+                break;
+            };
+
+            // Snapshots are only taken between passes, which `next_pass`
+            // keeps short enough to come about as often as asked for.
+            if let Some(progressive) = self.progressive
+                && progressive.snapshot_due(pass, last_snapshot.elapsed())
+            {
+                snapshot(&film.to_image(), samples.end);
+                last_snapshot = Instant::now();
+            }
+            samples = next;
         }
 
-        image_buffer
+        // Scaled to the samples taken, which a time limit can cut short.
+        let mut sample_counts = GrayImage::new(width, height);
+        for (i, pixel) in statistics.iter().enumerate() {
+            let level = pixel.count as f64 / samples.end as f64 * 255.0;
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            sample_counts.put_pixel(x, y, Luma([level.round() as u8]));
+        }
+
+        (film.to_image(), sample_counts)
     }
 
-    fn trace_ray(&self, ray: &crate::math::ray::Ray, scene: &Scene) -> Vec3<f64> {
+    // The sample indices for the pass after `done`: twice as many, but no
+    // more than are left, nor more than the last pass's pace says will fit
+    // in the time left or between snapshots. None when rendering is finished.
+    fn next_pass(
+        &self,
+        done: &Range<u32>,
+        elapsed: Duration,
+        last_pass: Duration,
+    ) -> Option<Range<u32>> {
+        let progressive = self.progressive?;
+        let seconds_per_sample = last_pass.as_secs_f64() / done.len() as f64;
+        let samples_within = |seconds: f64| (seconds / seconds_per_sample) as u32;
+
+        let mut size = (done.len() as u32 * 2).min(self.samples_per_pixel.saturating_sub(done.end));
+        if let Some(seconds) = progressive.snapshot_every_seconds {
+            // At least one sample, however slow, so the render moves on.
+            size = size.min(samples_within(seconds).max(1));
+        }
+        if let Some(time_limit) = self.time_limit {
+            let seconds_left = time_limit.saturating_sub(elapsed).as_secs_f64();
+            size = size.min(samples_within(seconds_left));
+        }
+        (size > 0).then(|| done.end..done.end + size)
+    }
+
+    // Takes the given range of sample indices in every pixel and splats them
+    // into `film`. Rows are rendered in blocks so only one block's bands are
+    // held at a time, and the bands are merged in row order so the result
+    // doesn't depend on which thread finished first.
+    fn render_pass(
+        &self,
+        scene: &Scene,
+        filter: Filter,
+        film: &mut Film,
+        samples: Range<u32>,
+        statistics: &mut [PixelStatistics],
+        pb: &ProgressBar,
+    ) {
+        let width = scene.camera.width as usize;
+        for (block, block_statistics) in statistics.chunks_mut(width * ROWS_PER_BLOCK).enumerate() {
+            let first_row = (block * ROWS_PER_BLOCK) as u32;
+            let bands = self.render_rows(
+                scene,
+                filter,
+                first_row,
+                samples.clone(),
+                block_statistics,
+                pb,
+            );
+            for band in &bands {
+                film.merge(band);
+            }
+        }
+    }
+
+    // Renders the rows starting at `first_row` that `statistics` covers,
+    // returning the film band each row splatted into.
+    fn render_rows(
+        &self,
+        scene: &Scene,
+        filter: Filter,
+        first_row: u32,
+        samples: Range<u32>,
+        statistics: &mut [PixelStatistics],
+        pb: &ProgressBar,
+    ) -> Vec<Film> {
+        let (width, height) = (scene.camera.width, scene.camera.height);
+        let samples_per_pixel = self.samples_per_pixel.max(1);
+
+        statistics
+            .par_chunks_mut(width as usize)
+            .enumerate()
+            .map_init(
+                || {
+                    let mut sampler =
+                        self.sampler
+                            .build(samples_per_pixel, (width, height), self.seed);
+                    sampler.start_pass(samples.clone());
+                    sampler
+                },
+                |sampler, (py, row)| {
+                    let py = first_row + py as u32;
+                    let mut band = Film::band_for_row(width, height, py, filter);
+                    for (px, pixel) in row.iter_mut().enumerate() {
+                        pb.inc(1);
+                        self.render_pixel(
+                            scene,
+                            sampler.as_mut(),
+                            (px as u32, py),
+                            samples.clone(),
+                            pixel,
+                            &mut band,
+                        );
+                    }
+                    band
+                },
+            )
+            .collect()
+    }
+
+    // Splats one pixel's samples into `film`, stopping early once adaptive
+    // sampling is satisfied with it.
+    fn render_pixel(
+        &self,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        (px, py): (u32, u32),
+        samples: Range<u32>,
+        statistics: &mut PixelStatistics,
+        film: &mut Film,
+    ) {
+        let exposure = scene.camera.exposure();
+
+        for index in samples {
+            if let Some(adaptive) = self.adaptive
+                && statistics.count >= adaptive.min_samples
+                && statistics.display_error() < adaptive.noise_threshold
+            {
+                break;
+            }
+
+            sampler.start_pixel_sample((px, py), index);
+
+            let (jitter_x, jitter_y) = sampler.get_2d();
+            let (x, y) = (px as f64 + jitter_x, py as f64 + jitter_y);
+
+            let sample = CameraSample {
+                film: scene.camera.film_position(x, y),
+                lens: sampler.get_2d(),
+                time: sampler.get_1d(),
+            };
+            // Film outside the camera's view stays black.
+            let radiance = match scene.camera.get_ray(&sample) {
+                Some(ray) => self.trace_ray(&ray, scene, sampler) * exposure,
+                None => Vec3::new(0.0, 0.0, 0.0),
+            };
+            statistics.add(radiance);
+            film.add_sample((x, y), radiance);
+        }
+    }
+
+    fn trace_ray(
+        &self,
+        ray: &crate::math::ray::Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+    ) -> Vec3<f64> {
         const T_MIN: f64 = 0.001;
         const T_MAX: f64 = f64::INFINITY;
 
-        let closest_hit = scene
-            .hittables
-            .iter()
-            .filter_map(|hittable| hittable.hit(ray, T_MIN, T_MAX))
-            .min_by(|a, b| a.t.total_cmp(&b.t));
+        if let Some((object, hit)) = scene.closest_hit(ray, T_MIN, T_MAX) {
+            return hit.material.shade(ray, &hit, object, scene, sampler);
+        }
+
+        scene.background.radiance(ray.direction)
+    }
+}
+
+// Welford's running mean and variance of the luminance of a pixel's samples.
+#[derive(Clone, Default)]
+struct PixelStatistics {
+    count: u32,
+    mean_luminance: f64,
+    squared_deviations: f64,
+}
+
+impl PixelStatistics {
+    fn add(&mut self, radiance: Vec3<f64>) {
+        self.count += 1;
+        let y = luminance(radiance);
+        let delta = y - self.mean_luminance;
+        self.mean_luminance += delta / self.count as f64;
+        self.squared_deviations += delta * (y - self.mean_luminance);
+    }
+
+    // Standard error of the mean luminance carried through the 0.5 gamma the
+    // image is written with, so dark and bright pixels are judged by how
+    // visible their noise is.
+    fn display_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.squared_deviations / (self.count - 1) as f64;
+        let standard_error = (variance / self.count as f64).sqrt();
+        // d(sqrt(y)) = dy / (2 sqrt(y)); the floor keeps near-black pixels
+        // from demanding endless samples.
+        standard_error / (2.0 * self.mean_luminance.max(1e-4).sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if let Some(hit) = closest_hit {
-            return hit.material.shade(&hit, scene);
+    #[test]
+    fn test_passes_double_until_samples_or_time_run_out() {
+        let renderer = Renderer::new(10).with_progressive(Progressive::default());
+        let (mut pass, mut passes) = (0..1, Vec::new());
+        while let Some(next) = renderer.next_pass(&pass, Duration::ZERO, Duration::ZERO) {
+            passes.push(pass);
+            pass = next;
         }
+        passes.push(pass);
+        assert_eq!(passes, [0..1, 1..3, 3..7, 7..10]);
+
+        // With 3s left and 4 samples having taken 2s, only 6 more fit.
+        let renderer = Renderer::new(100).with_time_limit(Duration::from_secs(10));
+        let next = renderer.next_pass(&(3..7), Duration::from_secs(7), Duration::from_secs(2));
+        assert_eq!(next, Some(7..13));
+        assert_eq!(
+            renderer.next_pass(&(3..7), Duration::from_secs(10), Duration::from_secs(2)),
+            None
+        );
+
+        // Snapshots every 1s leave room for 2 of them, but never fewer than 1.
+        let renderer = Renderer::new(100).with_progressive(Progressive {
+            snapshot_every_passes: None,
+            snapshot_every_seconds: Some(1.0),
+        });
+        let next = renderer.next_pass(&(3..7), Duration::ZERO, Duration::from_secs(2));
+        assert_eq!(next, Some(7..9));
+        let next = renderer.next_pass(&(7..9), Duration::ZERO, Duration::from_secs(5));
+        assert_eq!(next, Some(9..10));
+    }
 
-        Vec3::new(
-            scene.background_color[0] as f64 / 255.0,
-            scene.background_color[1] as f64 / 255.0,
-            scene.background_color[2] as f64 / 255.0,
-        )
+    #[test]
+    fn test_display_error_shrinks_with_samples() {
+        let mut statistics = PixelStatistics::default();
+        statistics.add(Vec3::new(0.5, 0.5, 0.5));
+        assert_eq!(statistics.display_error(), f64::INFINITY);
+
+        let mut errors = Vec::new();
+        for i in 1..64 {
+            let y = if i % 2 == 0 { 0.4 } else { 0.6 };
+            statistics.add(Vec3::new(y, y, y));
+            errors.push(statistics.display_error());
+        }
+        assert!(errors[62] < errors[8] && errors[8] < errors[2]);
+        assert!((statistics.mean_luminance - 0.5).abs() < 0.01);
+
+        // Identical samples have nothing left to resolve.
+        let mut flat = PixelStatistics::default();
+        for _ in 0..4 {
+            flat.add(Vec3::new(0.2, 0.2, 0.2));
+        }
+        assert!(flat.display_error() < 1e-9);
     }
 }
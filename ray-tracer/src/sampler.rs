@@ -0,0 +1,617 @@
+use clap::ValueEnum;
+use rand::Rng;
+use rand_pcg::Pcg32;
+use std::ops::Range;
+
+// Largest f64 below one, so scaled integer samples never reach 1.
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// Hands out the random numbers for one pixel sample at a time. Every call
+// after `start_pixel_sample` moves on to the next dimension, so callers draw
+// values in the same order for every sample: the film position, the lens, the
+// time, then light selection. Samplers that spread a pixel's samples evenly do
+// so dimension by dimension, which only works if that order holds.
+pub trait Sampler {
+    // The sample indices about to be taken in every pixel. Samplers that need
+    // a fixed sample count to spread samples out use it, so that a render cut
+    // short after any pass is still evenly sampled.
+    fn start_pass(&mut self, _samples: Range<u32>) {}
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum SamplerKind {
+    // Uncorrelated uniform random numbers.
+    Independent,
+    // Jittered strata, shuffled independently per dimension, and correlated
+    // multi-jitter for pairs. Works for any sample count, stratifying each
+    // pass on its own.
+    Stratified,
+    // Owen-scrambled Halton points, one prime base per dimension.
+    Halton,
+    // Owen-scrambled Sobol points, padded dimension by dimension. Every
+    // power of two run of samples is stratified, however many are taken.
+    #[default]
+    Sobol,
+    // Sobol points shared out between pixels along a Morton curve, which
+    // leaves the remaining error as blue noise across the image.
+    BlueNoise,
+}
+
+impl SamplerKind {
+    // `resolution` is the image size in pixels. Every value a sampler hands
+    // out depends only on the seed, the pixel, the sample index and the
+    // dimension, so renders repeat exactly whatever the thread count.
+    pub fn build(
+        self,
+        samples_per_pixel: u32,
+        resolution: (u32, u32),
+        seed: u64,
+    ) -> Box<dyn Sampler> {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerKind::BlueNoise => {
+                Box::new(BlueNoiseSampler::new(samples_per_pixel, resolution, seed))
+            }
+        }
+    }
+}
+
+pub struct IndependentSampler {
+    state: SampleState,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: SampleState::new(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.state.rng.r#gen()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.state.rng.r#gen(), self.state.rng.r#gen())
+    }
+}
+
+// Room in each pixel's random stream for one sample's worth of values.
+const STREAM_VALUES_PER_SAMPLE: u64 = 65536;
+
+// Where a sampler is up to within a pixel.
+struct SampleState {
+    seed: u64,
+    pixel: (u32, u32),
+    index: u32,
+    dimension: u32,
+    // A PCG stream of its own for each pixel, moved on to the start of the
+    // current sample's stretch of values.
+    rng: Pcg32,
+}
+
+impl SampleState {
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+            rng: Pcg32::new(0, 0),
+        }
+    }
+
+    fn start(&mut self, pixel: (u32, u32), index: u32) {
+        const PCG_DEFAULT_STATE: u64 = 0x853c49e6748fea9b;
+
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+        let stream = hash(&[pixel.0 as u64, pixel.1 as u64, self.seed]);
+        self.rng = Pcg32::new(PCG_DEFAULT_STATE, stream);
+        self.rng.advance(index as u64 * STREAM_VALUES_PER_SAMPLE);
+    }
+
+    // A hash of the pixel, the current dimension and the seed, then moves on
+    // `count` dimensions.
+    fn next_hash(&mut self, count: u32) -> u64 {
+        let hash = hash(&[
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.dimension as u64,
+            self.seed,
+        ]);
+        self.dimension += count;
+        hash
+    }
+}
+
+pub struct StratifiedSampler {
+    // The samples being stratified, one stratum each.
+    samples: Range<u32>,
+    // A grid with at least one cell per sample for 2D dimensions.
+    columns: u32,
+    rows: u32,
+    state: SampleState,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        let mut sampler = Self {
+            samples: 0..1,
+            columns: 1,
+            rows: 1,
+            state: SampleState::new(seed),
+        };
+        sampler.start_pass(0..samples_per_pixel);
+        sampler
+    }
+
+    // The current sample's place among the samples being stratified.
+    fn sample_in_pass(&self) -> u32 {
+        self.state.index.saturating_sub(self.samples.start) % self.samples.len() as u32
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pass(&mut self, samples: Range<u32>) {
+        let count = (samples.len() as u32).max(1);
+        self.samples = samples.start..samples.start + count;
+        self.columns = ((count as f64).sqrt() as u32).max(1);
+        self.rows = count.div_ceil(self.columns);
+    }
+
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = self.state.next_hash(1);
+        let count = self.samples.len() as u32;
+        let stratum = permutation_element(self.sample_in_pass(), count, hash as u32);
+        (stratum as f64 + self.state.rng.r#gen::<f64>()) / count as f64
+    }
+
+    // Kensler's correlated multi-jitter ("Correlated Multi-Jittered
+    // Sampling", 2013): one sample per grid cell, and the offsets within cells
+    // arranged so that each column and row is also stratified along its
+    // length. Sample counts that do not fill the grid leave some cells empty.
+    fn get_2d(&mut self) -> (f64, f64) {
+        let hash = self.state.next_hash(2) as u32;
+        let (m, n) = (self.columns, self.rows);
+        let s = permutation_element(self.sample_in_pass(), self.samples.len() as u32, hash);
+        let sx = permutation_element(s % m, m, hash.wrapping_mul(0x68bc21eb));
+        let sy = permutation_element(s / m, n, hash.wrapping_mul(0x02e5be93));
+        let rng = &mut self.state.rng;
+        let (jx, jy) = (rng.r#gen::<f64>(), rng.r#gen::<f64>());
+        (
+            ((s % m) as f64 + (sy as f64 + jx) / n as f64) / m as f64,
+            ((s / m) as f64 + (sx as f64 + jy) / m as f64) / n as f64,
+        )
+    }
+}
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// Each pixel gets its own scrambling of the same Halton points. Dimensions past
+// the prime table reuse its bases with fresh scrambles.
+pub struct HaltonSampler {
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: SampleState::new(seed),
+        }
+    }
+
+    fn sample_dimension(&mut self) -> f64 {
+        let base = PRIMES[self.state.dimension as usize % PRIMES.len()];
+        let hash = self.state.next_hash(1);
+        owen_scrambled_radical_inverse(self.state.index as u64, base, hash)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.sample_dimension()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.sample_dimension(), self.sample_dimension())
+    }
+}
+
+// Uses the first two Sobol dimensions for every pair of sample dimensions,
+// with the sample order shuffled and the points Owen-scrambled per pixel and
+// dimension so that pairs stay uncorrelated (PBRT's padded Sobol sampler).
+// The order is shuffled by Owen-scrambling the index too, which only swaps
+// aligned runs of samples with each other, so the first 2^k samples are
+// always one such run and cover the pixel evenly.
+pub struct SobolSampler {
+    state: SampleState,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: SampleState::new(seed),
+        }
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = self.state.next_hash(1);
+        let index = fast_owen_scramble(self.state.index, hash as u32);
+        sobol_sample(index as u64, 0, (hash >> 32) as u32)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let hash = self.state.next_hash(2);
+        let index = fast_owen_scramble(self.state.index, hash as u32);
+        let scramble = mix_bits(hash);
+        (
+            sobol_sample(index as u64, 0, scramble as u32),
+            sobol_sample(index as u64, 1, (scramble >> 32) as u32),
+        )
+    }
+}
+
+// Ahmed and Wonka's ordering of one long Sobol sequence across the image,
+// "Screen-Space Blue-Noise Diffusion of Monte Carlo Sampling Error via
+// Hierarchical Ordering of Pixels" (2020), as in PBRT's ZSobol sampler.
+// Neighbouring pixels get complementary points, so their errors cancel out
+// at a glance.
+pub struct BlueNoiseSampler {
+    log2_samples_per_pixel: u32,
+    base4_digits: u32,
+    morton_index: u64,
+    dimension: u32,
+    seed: u64,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(samples_per_pixel: u32, resolution: (u32, u32), seed: u64) -> Self {
+        let log2_samples_per_pixel = samples_per_pixel.next_power_of_two().trailing_zeros();
+        let log2_resolution = resolution
+            .0
+            .max(resolution.1)
+            .next_power_of_two()
+            .trailing_zeros();
+        Self {
+            log2_samples_per_pixel,
+            base4_digits: log2_resolution + log2_samples_per_pixel.div_ceil(2),
+            morton_index: 0,
+            dimension: 0,
+            seed,
+        }
+    }
+
+    // Shuffles the base 4 digits of the Morton index, each by a permutation
+    // picked from the digits above it, so every level of the quadtree of
+    // pixels and samples is visited in a random but balanced order.
+    fn sample_index(&self) -> u64 {
+        const PERMUTATIONS: [[u64; 4]; 24] = [
+            [0, 1, 2, 3],
+            [0, 1, 3, 2],
+            [0, 2, 1, 3],
+            [0, 2, 3, 1],
+            [0, 3, 2, 1],
+            [0, 3, 1, 2],
+            [1, 0, 2, 3],
+            [1, 0, 3, 2],
+            [1, 2, 0, 3],
+            [1, 2, 3, 0],
+            [1, 3, 2, 0],
+            [1, 3, 0, 2],
+            [2, 1, 0, 3],
+            [2, 1, 3, 0],
+            [2, 0, 1, 3],
+            [2, 0, 3, 1],
+            [2, 3, 0, 1],
+            [2, 3, 1, 0],
+            [3, 1, 2, 0],
+            [3, 1, 0, 2],
+            [3, 2, 1, 0],
+            [3, 2, 0, 1],
+            [3, 0, 2, 1],
+            [3, 0, 1, 2],
+        ];
+
+        let dimension_key = 0x5555_5555u64.wrapping_mul(self.dimension as u64);
+        // An odd power of two leaves one base 2 digit at the bottom.
+        let odd = self.log2_samples_per_pixel & 1;
+        let mut index = 0;
+        for i in (odd..self.base4_digits).rev() {
+            let shift = 2 * i - odd;
+            let digit = (self.morton_index >> shift) & 3;
+            let higher_digits = self.morton_index >> (shift + 2);
+            let p = (mix_bits(higher_digits ^ dimension_key) >> 24) % 24;
+            index |= PERMUTATIONS[p as usize][digit as usize] << shift;
+        }
+        if odd == 1 {
+            let digit = self.morton_index & 1;
+            index |= digit ^ (mix_bits((self.morton_index >> 1) ^ dimension_key) & 1);
+        }
+        index
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.morton_index =
+            (encode_morton(pixel.0, pixel.1) << self.log2_samples_per_pixel) | index as u64;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let index = self.sample_index();
+        self.dimension += 1;
+        let hash = hash(&[self.dimension as u64, self.seed]);
+        sobol_sample(index, 0, hash as u32)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let index = self.sample_index();
+        self.dimension += 2;
+        let hash = hash(&[self.dimension as u64, self.seed]);
+        (
+            sobol_sample(index, 0, hash as u32),
+            sobol_sample(index, 1, (hash >> 32) as u32),
+        )
+    }
+}
+
+// Generator matrices for the first two Sobol dimensions: the van der Corput
+// sequence and the one from the polynomial x + 1.
+const SOBOL_MATRICES: [[u32; 32]; 2] = {
+    let mut matrices = [[0u32; 32]; 2];
+    let mut i = 0;
+    while i < 32 {
+        matrices[0][i] = 1 << (31 - i);
+        matrices[1][i] = if i == 0 {
+            1 << 31
+        } else {
+            matrices[1][i - 1] ^ (matrices[1][i - 1] >> 1)
+        };
+        i += 1;
+    }
+    matrices
+};
+
+fn sobol_sample(mut index: u64, dimension: usize, scramble: u32) -> f64 {
+    let mut bits = 0;
+    let mut row = 0;
+    while index != 0 && row < 32 {
+        if index & 1 != 0 {
+            bits ^= SOBOL_MATRICES[dimension][row];
+        }
+        index >>= 1;
+        row += 1;
+    }
+    (fast_owen_scramble(bits, scramble) as f64 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+// Laine and Karras' hash-based approximation of Owen scrambling: each bit is
+// flipped depending only on the bits above it.
+fn fast_owen_scramble(v: u32, seed: u32) -> u32 {
+    let mut v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x05526c56);
+    v ^= v.wrapping_mul(0x53a22864);
+    v.reverse_bits()
+}
+
+// The radical inverse of `a` in `base`, with each digit permuted by a hash of
+// the digits before it.
+fn owen_scrambled_radical_inverse(mut a: u64, base: u64, hash: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut reversed_digits = 0u64;
+    let mut inverse_base_m = 1.0;
+    // Stop once further digits can no longer change the result.
+    while 1.0 - (base - 1) as f64 * inverse_base_m < 1.0 {
+        let next = a / base;
+        let digit = a - next * base;
+        let digit_hash = mix_bits(hash ^ reversed_digits);
+        let digit = permutation_element(digit as u32, base as u32, digit_hash as u32) as u64;
+        reversed_digits = reversed_digits * base + digit;
+        inverse_base_m *= inverse_base;
+        a = next;
+    }
+    (inverse_base_m * reversed_digits as f64).min(ONE_MINUS_EPSILON)
+}
+
+// Element `i` of a random permutation of 0..`l` chosen by `p`, without
+// building the permutation (Kensler, "Correlated Multi-Jittered Sampling").
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            return i.wrapping_add(p) % l;
+        }
+    }
+}
+
+fn encode_morton(x: u32, y: u32) -> u64 {
+    fn spread(v: u32) -> u64 {
+        let mut v = v as u64;
+        v = (v | (v << 16)) & 0x0000_ffff_0000_ffff;
+        v = (v | (v << 8)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+        (v | (v << 1)) & 0x5555_5555_5555_5555
+    }
+    (spread(y) << 1) | spread(x)
+}
+
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |h, &v| {
+        mix_bits(h ^ v.wrapping_add(0x9e3779b97f4a7c15))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samplers_stratify_each_dimension() {
+        // With 16 samples these put exactly one sample in each sixteenth of a
+        // 1D dimension. Halton only does so in its first, base 2, dimension.
+        for (kind, dimensions) in [
+            (SamplerKind::Stratified, 4),
+            (SamplerKind::Halton, 1),
+            (SamplerKind::Sobol, 4),
+            (SamplerKind::BlueNoise, 4),
+        ] {
+            let mut sampler = kind.build(16, (64, 64), 0);
+            for dimension in 0..dimensions {
+                let mut strata = [0; 16];
+                for index in 0..16 {
+                    sampler.start_pixel_sample((5, 9), index);
+                    for _ in 0..dimension {
+                        sampler.get_1d();
+                    }
+                    let u = sampler.get_1d();
+                    assert!((0.0..1.0).contains(&u));
+                    strata[(u * 16.0) as usize] += 1;
+                }
+                assert_eq!(strata, [1; 16], "{:?} dimension {}", kind, dimension);
+            }
+        }
+    }
+
+    #[test]
+    fn test_passes_are_stratified_whatever_the_sample_count() {
+        // A render allowed 65536 samples that stops after 16 or 32 of them,
+        // as a time limit or adaptive sampling may do.
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Sobol,
+            SamplerKind::BlueNoise,
+        ] {
+            let mut sampler = kind.build(1 << 16, (64, 64), 0);
+            for pass in [0..16, 16..32] {
+                sampler.start_pass(pass.clone());
+                let (mut xs, mut ys, mut cells) = ([0; 16], [0; 16], [0; 16]);
+                for index in pass {
+                    sampler.start_pixel_sample((5, 9), index);
+                    let (x, y) = sampler.get_2d();
+                    let z = sampler.get_1d();
+                    xs[(x * 16.0) as usize] += 1;
+                    ys[(z * 16.0) as usize] += 1;
+                    cells[(y * 4.0) as usize * 4 + (x * 4.0) as usize] += 1;
+                }
+                assert_eq!((xs, ys, cells), ([1; 16], [1; 16], [1; 16]), "{:?}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn test_multi_jitter_stratifies_any_count() {
+        // Twelve samples fill a 3 by 4 grid, one per cell, and each axis on
+        // its own in twelfths.
+        let mut sampler = StratifiedSampler::new(12, 3);
+        let (mut cells, mut xs, mut ys) = ([0; 12], [0; 12], [0; 12]);
+        for index in 0..12 {
+            sampler.start_pixel_sample((4, 1), index);
+            let (x, y) = sampler.get_2d();
+            cells[(y * 4.0) as usize * 3 + (x * 3.0) as usize] += 1;
+            xs[(x * 12.0) as usize] += 1;
+            ys[(y * 12.0) as usize] += 1;
+        }
+        assert_eq!((cells, xs, ys), ([1; 12], [1; 12], [1; 12]));
+    }
+
+    #[test]
+    fn test_samples_depend_only_on_seed_pixel_and_index() {
+        let draw = |seed, pixel, index| {
+            let mut sampler = SamplerKind::Independent.build(4, (8, 8), seed);
+            sampler.start_pixel_sample(pixel, index);
+            (sampler.get_1d(), sampler.get_2d())
+        };
+
+        // Visiting other pixels first, as another thread might, changes
+        // nothing.
+        let mut sampler = SamplerKind::Independent.build(4, (8, 8), 7);
+        sampler.start_pixel_sample((3, 3), 1);
+        sampler.get_2d();
+        sampler.start_pixel_sample((1, 2), 3);
+        assert_eq!((sampler.get_1d(), sampler.get_2d()), draw(7, (1, 2), 3));
+
+        assert_ne!(draw(7, (1, 2), 3), draw(8, (1, 2), 3));
+        assert_ne!(draw(7, (1, 2), 3), draw(7, (2, 1), 3));
+        assert_ne!(draw(7, (1, 2), 3), draw(7, (1, 2), 2));
+    }
+
+    #[test]
+    fn test_permutation_element_is_a_permutation() {
+        for l in [1, 7, 16, 50] {
+            let mut seen: Vec<u32> = (0..l)
+                .map(|i| permutation_element(i, l, 0xdeadbeef))
+                .collect();
+            seen.sort();
+            assert_eq!(seen, (0..l).collect::<Vec<_>>());
+        }
+    }
+}
@@ -1,28 +1,106 @@
-use crate::{camera::Camera, hittable::Hittable, light::PointLight, math::vec3::Vec3};
-use image::Rgb;
+use crate::{
+    camera::Camera,
+    environment::Environment,
+    filter::Filter,
+    hittable::{HitRecord, Hittable},
+    light::Light,
+    light_sampler::LightSampler,
+    math::{ray::Ray, vec3::Vec3},
+};
+use std::sync::Arc;
+
+// A hittable with per-object visibility and light linking.
+pub struct SceneObject {
+    pub hittable: Box<dyn Hittable>,
+    pub casts_shadows: bool,
+    pub visible_to_camera: bool,
+    // One flag per entry in `Scene::lights`; None when every light applies.
+    light_mask: Option<Vec<bool>>,
+}
+
+impl SceneObject {
+    pub fn new(hittable: Box<dyn Hittable>) -> Self {
+        Self {
+            hittable,
+            casts_shadows: true,
+            visible_to_camera: true,
+            light_mask: None,
+        }
+    }
+
+    // Restricts the object to being lit by the lights whose indices are set.
+    pub fn with_light_mask(mut self, light_mask: Vec<bool>) -> Self {
+        self.light_mask = Some(light_mask);
+        self
+    }
+
+    pub fn is_lit_by(&self, light_index: usize) -> bool {
+        self.light_mask
+            .as_ref()
+            .is_none_or(|mask| mask.get(light_index).copied().unwrap_or(false))
+    }
+}
 
 pub struct Scene {
     pub camera: Camera,
-    pub lights: Vec<PointLight>,
-    pub hittables: Vec<Box<dyn Hittable>>,
-    pub background_color: Rgb<u8>,
+    pub lights: Vec<Box<dyn Light>>,
+    pub light_sampler: LightSampler,
+    pub objects: Vec<SceneObject>,
+    pub background: Arc<dyn Environment>,
     pub ambient_light: Vec3<f64>,
+    // Pixel filter asked for by the scene file, if any.
+    pub filter: Option<Filter>,
 }
 
 impl Scene {
     pub fn new(
         camera: Camera,
-        lights: Vec<PointLight>,
-        hittables: Vec<Box<dyn Hittable>>,
-        background_color: Rgb<u8>,
+        lights: Vec<Box<dyn Light>>,
+        objects: Vec<SceneObject>,
+        background: Arc<dyn Environment>,
         ambient_light: Vec3<f64>,
     ) -> Self {
+        let light_sampler = LightSampler::new(&lights);
         Self {
             camera,
             lights,
-            hittables,
-            background_color,
+            light_sampler,
+            objects,
+            background,
             ambient_light,
+            filter: None,
         }
     }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    // The nearest hit among the objects visible to the camera.
+    pub fn closest_hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(&SceneObject, HitRecord)> {
+        let mut closest = None;
+        let mut closest_so_far = t_max;
+
+        for object in self.objects.iter().filter(|o| o.visible_to_camera) {
+            if let Some(hit) = object.hittable.hit(ray, t_min, closest_so_far) {
+                closest_so_far = hit.t;
+                closest = Some((object, hit));
+            }
+        }
+        closest
+    }
+
+    // Whether any shadow-casting object blocks `ray` before `t_max`.
+    pub fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.objects
+            .iter()
+            .filter(|o| o.casts_shadows)
+            .any(|o| o.hittable.hit(ray, t_min, t_max).is_some())
+    }
 }
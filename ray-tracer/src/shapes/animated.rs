@@ -0,0 +1,130 @@
+use crate::{
+    hittable::{HitRecord, Hittable},
+    math::{ray::Ray, vec3::Vec3},
+};
+
+// Placement of an animated object at one moment. The object is scaled, then
+// rotated about the X, Y and Z axes in turn, then translated, all about the
+// scene origin.
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub translation: Vec3<f64>,
+    // Euler angles in radians.
+    pub rotation: Vec3<f64>,
+    pub scale: f64,
+}
+
+impl Keyframe {
+    fn lerp(&self, other: &Keyframe, t: f64) -> Keyframe {
+        Keyframe {
+            time: self.time + (other.time - self.time) * t,
+            translation: self.translation + (other.translation - self.translation) * t,
+            rotation: self.rotation + (other.rotation - self.rotation) * t,
+            scale: self.scale + (other.scale - self.scale) * t,
+        }
+    }
+
+    fn rotate(&self, v: Vec3<f64>) -> Vec3<f64> {
+        let v = rotate_axis(v, 0, self.rotation.x);
+        let v = rotate_axis(v, 1, self.rotation.y);
+        rotate_axis(v, 2, self.rotation.z)
+    }
+
+    fn unrotate(&self, v: Vec3<f64>) -> Vec3<f64> {
+        let v = rotate_axis(v, 2, -self.rotation.z);
+        let v = rotate_axis(v, 1, -self.rotation.y);
+        rotate_axis(v, 0, -self.rotation.x)
+    }
+}
+
+// Moves an object along keyframes, interpolating linearly between them and
+// holding the first and last outside their range. Rays are moved into the
+// object's frame at their own time, so the object blurs over the shutter.
+pub struct Animated {
+    inner: Box<dyn Hittable>,
+    keyframes: Vec<Keyframe>,
+}
+
+impl Animated {
+    pub fn new(inner: Box<dyn Hittable>, mut keyframes: Vec<Keyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { inner, keyframes }
+    }
+
+    fn keyframe_at(&self, time: f64) -> Keyframe {
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        match next {
+            0 => self.keyframes[0],
+            n if n == self.keyframes.len() => self.keyframes[n - 1],
+            n => {
+                let (a, b) = (&self.keyframes[n - 1], &self.keyframes[n]);
+                a.lerp(b, (time - a.time) / (b.time - a.time))
+            }
+        }
+    }
+}
+
+impl Hittable for Animated {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if self.keyframes.is_empty() {
+            return self.inner.hit(ray, t_min, t_max);
+        }
+
+        // Scaling the direction along with the origin keeps `t` the same in
+        // both frames.
+        let key = self.keyframe_at(ray.time);
+        let local = Ray::new(
+            key.unrotate(ray.origin - key.translation) / key.scale,
+            key.unrotate(ray.direction) / key.scale,
+            ray.time,
+        );
+
+        let mut hit = self.inner.hit(&local, t_min, t_max)?;
+        hit.point = ray.at(hit.t);
+        hit.normal = key.rotate(hit.normal).normalize();
+        Some(hit)
+    }
+}
+
+fn rotate_axis(v: Vec3<f64>, axis: usize, angle: f64) -> Vec3<f64> {
+    let (sin, cos) = angle.sin_cos();
+    match axis {
+        0 => Vec3::new(v.x, v.y * cos - v.z * sin, v.y * sin + v.z * cos),
+        1 => Vec3::new(v.x * cos + v.z * sin, v.y, -v.x * sin + v.z * cos),
+        _ => Vec3::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos, v.z),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, shapes::sphere::Sphere};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_hit_follows_keyframes() {
+        let sphere = Sphere::new(
+            Vec3::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::new(image::Rgb([255, 255, 255]))),
+        );
+        let key = |time, x| Keyframe {
+            time,
+            translation: Vec3::new(x, 0.0, 0.0),
+            rotation: Vec3::new(0.0, 0.0, 0.0),
+            scale: 2.0,
+        };
+        let animated = Animated::new(Box::new(sphere), vec![key(1.0, 10.0), key(0.0, 0.0)]);
+
+        // Halfway through, the sphere of radius 2 is centred at x = 5.
+        let ray = Ray::new(Vec3::new(5.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0), 0.5);
+        let hit = animated.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - 8.0).abs() < 1e-9);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+
+        // Before the first keyframe it stays at the origin.
+        let ray = Ray::new(Vec3::new(5.0, 0.0, 10.0), Vec3::new(0.0, 0.0, -1.0), -1.0);
+        assert!(animated.hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -1,3 +1,5 @@
+pub mod animated;
 pub mod mesh;
+pub mod point_cloud;
 pub mod sphere;
 pub mod triangle;
@@ -0,0 +1,221 @@
+use crate::{
+    hittable::{HitRecord, Hittable},
+    material::{Lambertian, Material},
+    math::{aabb::Aabb, ray::Ray, vec3::Vec3},
+};
+use image::Rgb;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const MAX_POINTS_PER_LEAF: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SplatShape {
+    Sphere,
+    // A disk facing along the point's normal, hit from either side.
+    Disk,
+}
+
+struct Splat {
+    center: Vec3<f64>,
+    normal: Vec3<f64>,
+    radius: f64,
+    material: Arc<dyn Material>,
+}
+
+enum BvhNode {
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        end: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+pub struct PointCloud {
+    splats: Vec<Splat>,
+    nodes: Vec<BvhNode>,
+    shape: SplatShape,
+}
+
+impl PointCloud {
+    // `normals` is required for disk splats. Points without a colour use
+    // `material`; coloured points are shaded as Lambertian with their colour.
+    pub fn new(
+        positions: Vec<Vec3<f64>>,
+        normals: Option<Vec<Vec3<f64>>>,
+        colors: Option<Vec<Rgb<u8>>>,
+        radii: Option<Vec<f64>>,
+        default_radius: f64,
+        shape: SplatShape,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        // Scans tend to repeat colours, so points share one material each.
+        let mut materials: HashMap<Rgb<u8>, Arc<dyn Material>> = HashMap::new();
+        let splats = positions
+            .into_iter()
+            .enumerate()
+            .map(|(i, center)| Splat {
+                center,
+                normal: normals.as_ref().map_or(Vec3::new(0.0, 1.0, 0.0), |n| n[i]),
+                radius: radii.as_ref().map_or(default_radius, |r| r[i]),
+                material: match &colors {
+                    Some(colors) => Arc::clone(
+                        materials
+                            .entry(colors[i])
+                            .or_insert_with(|| Arc::new(Lambertian::new(colors[i]))),
+                    ),
+                    None => Arc::clone(&material),
+                },
+            })
+            .collect();
+
+        let mut cloud = Self {
+            splats,
+            nodes: Vec::new(),
+            shape,
+        };
+        if !cloud.splats.is_empty() {
+            let len = cloud.splats.len();
+            cloud.build_node(0, len);
+        }
+        cloud
+    }
+
+    pub fn len(&self) -> usize {
+        self.splats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.splats.is_empty()
+    }
+
+    // Builds the subtree over `splats[start..end]`, reordering the points in
+    // place, and returns the index of its root node.
+    fn build_node(&mut self, start: usize, end: usize) -> usize {
+        let bounds = self.splats[start..end].iter().fold(Aabb::empty(), |b, s| {
+            b.union(&Aabb::around_sphere(s.center, s.radius))
+        });
+
+        let index = self.nodes.len();
+        if end - start <= MAX_POINTS_PER_LEAF {
+            self.nodes.push(BvhNode::Leaf { bounds, start, end });
+            return index;
+        }
+
+        let centroid_bounds = self.splats[start..end]
+            .iter()
+            .fold(Aabb::empty(), |b, s| b.grow(s.center));
+        let axis = centroid_bounds.longest_axis();
+        let mid = start + (end - start) / 2;
+        self.splats[start..end].select_nth_unstable_by(mid - start, |a, b| {
+            a.center.axis(axis).total_cmp(&b.center.axis(axis))
+        });
+
+        // Reserve the slot so children land after their parent.
+        self.nodes.push(BvhNode::Leaf { bounds, start, end });
+        let left = self.build_node(start, mid);
+        let right = self.build_node(mid, end);
+        self.nodes[index] = BvhNode::Interior {
+            bounds,
+            left,
+            right,
+        };
+        index
+    }
+
+    fn intersect(&self, splat: &Splat, ray: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        match self.shape {
+            SplatShape::Sphere => {
+                let oc = ray.origin - splat.center;
+                let a = ray.direction.length_squared();
+                let half_b = oc.dot(&ray.direction);
+                let c = oc.length_squared() - splat.radius * splat.radius;
+                let discriminant = half_b * half_b - a * c;
+                if discriminant < 0.0 {
+                    return None;
+                }
+
+                let sqrtd = discriminant.sqrt();
+                [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a]
+                    .into_iter()
+                    .find(|&t| t >= t_min && t <= t_max)
+            }
+            SplatShape::Disk => {
+                const EPSILON: f64 = 1e-9;
+                let denom = splat.normal.dot(&ray.direction);
+                if denom.abs() < EPSILON {
+                    return None;
+                }
+
+                let t = (splat.center - ray.origin).dot(&splat.normal) / denom;
+                if t < t_min || t > t_max {
+                    return None;
+                }
+
+                let offset = ray.at(t) - splat.center;
+                (offset.length_squared() <= splat.radius * splat.radius).then_some(t)
+            }
+        }
+    }
+}
+
+impl Hittable for PointCloud {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut closest: Option<(&Splat, f64)> = None;
+        let mut closest_so_far = t_max;
+
+        let mut stack = [0usize; 64];
+        let mut stack_len = 1;
+
+        while stack_len > 0 {
+            stack_len -= 1;
+            match &self.nodes[stack[stack_len]] {
+                BvhNode::Leaf { bounds, start, end } => {
+                    if !bounds.hit(ray, t_min, closest_so_far) {
+                        continue;
+                    }
+                    for splat in &self.splats[*start..*end] {
+                        if let Some(t) = self.intersect(splat, ray, t_min, closest_so_far) {
+                            closest_so_far = t;
+                            closest = Some((splat, t));
+                        }
+                    }
+                }
+                BvhNode::Interior {
+                    bounds,
+                    left,
+                    right,
+                } => {
+                    if bounds.hit(ray, t_min, closest_so_far) {
+                        stack[stack_len] = *left;
+                        stack[stack_len + 1] = *right;
+                        stack_len += 2;
+                    }
+                }
+            }
+        }
+
+        let (splat, t) = closest?;
+        let point = ray.at(t);
+        let normal = match self.shape {
+            SplatShape::Sphere => (point - splat.center) / splat.radius,
+            SplatShape::Disk if splat.normal.dot(&ray.direction) > 0.0 => splat.normal * -1.0,
+            SplatShape::Disk => splat.normal,
+        };
+        Some(HitRecord {
+            t,
+            point,
+            normal,
+            material: Arc::clone(&splat.material),
+        })
+    }
+}
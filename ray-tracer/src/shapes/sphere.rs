@@ -8,6 +8,9 @@ pub struct Sphere {
     center: Vec3<f64>,
     radius: f64,
     material: Arc<dyn Material>,
+    // Distance the centre moves per unit of time, starting from `center` at
+    // time zero.
+    velocity: Vec3<f64>,
 }
 
 impl Sphere {
@@ -16,13 +19,24 @@ impl Sphere {
             center,
             radius,
             material,
+            velocity: Vec3::new(0.0, 0.0, 0.0),
         }
     }
+
+    pub fn with_velocity(mut self, velocity: Vec3<f64>) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    fn center_at(&self, time: f64) -> Vec3<f64> {
+        self.center + self.velocity * time
+    }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
+        let center = self.center_at(ray.time);
+        let oc = ray.origin - center;
         let a = ray.direction.length_squared();
         let half_b = oc.dot(&ray.direction);
         let c = oc.length_squared() - self.radius * self.radius;
@@ -44,7 +58,7 @@ impl Hittable for Sphere {
 
         let t = root;
         let point = ray.at(t);
-        let normal = (point - self.center) / self.radius;
+        let normal = (point - center) / self.radius;
 
         Some(HitRecord {
             t,
@@ -38,6 +38,7 @@ impl Triangle {
 }
 
 impl Hittable for Triangle {
+    #[allow(clippy::manual_range_contains)]
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         const EPSILON: f64 = 0.000001;
         let edge1 = self.v1 - self.v0;
@@ -0,0 +1,213 @@
+use crate::{environment::Environment, light::DirectionalLight, math::vec3::Vec3};
+use std::f64::consts::PI;
+
+// Extraterrestrial solar illuminance in klx, matching the kcd/m^2 the sky is
+// expressed in.
+const SOLAR_ILLUMINANCE: f64 = 128.0;
+const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+
+// The Preetham, Shirley and Smits analytic daylight model ("A Practical
+// Analytic Model for Daylight", 1999). Radiance is in kcd/m^2 scaled by
+// `intensity`; below the horizon the sky is replaced by a dim reflection of
+// the horizon.
+pub struct PreethamSky {
+    sun_direction: Vec3<f64>,
+    turbidity: f64,
+    intensity: f64,
+    ground_albedo: f64,
+    perez_y: [f64; 5],
+    perez_x: [f64; 5],
+    perez_yc: [f64; 5],
+    // Zenith luminance and chromaticity, pre-divided by the Perez function at
+    // the zenith.
+    zenith: [f64; 3],
+}
+
+impl PreethamSky {
+    // Elevation is measured up from the horizon and azimuth clockwise from
+    // -Z towards +X, both in degrees. Turbidity ranges from about 2 (very
+    // clear) to 10 (hazy).
+    pub fn new(
+        sun_elevation_degrees: f64,
+        sun_azimuth_degrees: f64,
+        turbidity: f64,
+        intensity: f64,
+        ground_albedo: f64,
+    ) -> Self {
+        let elevation = sun_elevation_degrees.clamp(-90.0, 90.0).to_radians();
+        let azimuth = sun_azimuth_degrees.to_radians();
+        let sun_direction = Vec3::new(
+            azimuth.sin() * elevation.cos(),
+            elevation.sin(),
+            -azimuth.cos() * elevation.cos(),
+        );
+
+        let t = turbidity.clamp(1.0, 20.0);
+        let perez_y = [
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ];
+        let perez_x = [
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ];
+        let perez_yc = [
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ];
+
+        // The model is only fitted for the sun above the horizon.
+        let theta_s = (PI / 2.0 - elevation).clamp(0.0, PI / 2.0);
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+
+        let th = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+        let dot4 = |a: [f64; 4]| a.iter().zip(&th).map(|(a, b)| a * b).sum::<f64>();
+        let zenith_x = t * t * dot4([0.00166, -0.00375, 0.00209, 0.0])
+            + t * dot4([-0.02903, 0.06377, -0.03202, 0.00394])
+            + dot4([0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_yc = t * t * dot4([0.00275, -0.00610, 0.00317, 0.0])
+            + t * dot4([-0.04214, 0.08970, -0.04153, 0.00516])
+            + dot4([0.15346, -0.26756, 0.06670, 0.26688]);
+
+        let zenith = [
+            zenith_y / perez(&perez_y, 0.0, theta_s),
+            zenith_x / perez(&perez_x, 0.0, theta_s),
+            zenith_yc / perez(&perez_yc, 0.0, theta_s),
+        ];
+
+        Self {
+            sun_direction,
+            turbidity: t,
+            intensity,
+            ground_albedo,
+            perez_y,
+            perez_x,
+            perez_yc,
+            zenith,
+        }
+    }
+
+    // A sun matching the sky, attenuated by Rayleigh and aerosol scattering
+    // along its path through the atmosphere.
+    pub fn sun(&self) -> Option<DirectionalLight> {
+        if self.sun_direction.y <= 0.0 {
+            return None;
+        }
+
+        let theta_s = self.sun_direction.y.acos();
+        let relative_air_mass =
+            1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        // Representative wavelengths for R, G and B in micrometres.
+        let transmittance = |lambda: f64| {
+            let rayleigh = (-0.008735 * lambda.powf(-4.08) * relative_air_mass).exp();
+            let aerosol = (-beta * lambda.powf(-1.3) * relative_air_mass).exp();
+            rayleigh * aerosol
+        };
+        let color = Vec3::new(
+            transmittance(0.65),
+            transmittance(0.57),
+            transmittance(0.475),
+        );
+
+        Some(DirectionalLight::new(
+            self.sun_direction * -1.0,
+            color,
+            SOLAR_ILLUMINANCE * self.intensity,
+            SUN_ANGULAR_DIAMETER,
+        ))
+    }
+
+    fn sky_radiance(&self, direction: Vec3<f64>) -> Vec3<f64> {
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let luminance = self.zenith[0] * perez(&self.perez_y, theta, gamma);
+        let x = self.zenith[1] * perez(&self.perez_x, theta, gamma);
+        let y = self.zenith[2] * perez(&self.perez_yc, theta, gamma);
+
+        xyy_to_rgb(x, y, luminance) * self.intensity
+    }
+}
+
+impl Environment for PreethamSky {
+    fn radiance(&self, direction: Vec3<f64>) -> Vec3<f64> {
+        const HORIZON_Y: f64 = 0.001;
+        if direction.y >= HORIZON_Y {
+            return self.sky_radiance(direction);
+        }
+
+        let horizon = Vec3::new(direction.x, HORIZON_Y, direction.z).normalize();
+        self.sky_radiance(horizon) * self.ground_albedo
+    }
+}
+
+// The Perez et al. all-weather luminance distribution.
+fn perez(coefficients: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coefficients;
+    let cos_theta = theta.cos().max(0.001);
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+// CIE xyY to linear sRGB.
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Vec3<f64> {
+    if y <= 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
+    }
+    let cx = x / y * luminance;
+    let cz = (1.0 - x - y) / y * luminance;
+    let cy = luminance;
+    Vec3::new(
+        (3.2406 * cx - 1.5372 * cy - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * cy + 0.0415 * cz).max(0.0),
+        (0.0557 * cx - 0.2040 * cy + 1.0570 * cz).max(0.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zenith_matches_preetham() {
+        // Turbidity 3 with the sun 45 degrees up, from the paper's formulas
+        // for zenith luminance in kcd/m^2 and chromaticity.
+        let sky = PreethamSky::new(45.0, 0.0, 3.0, 1.0, 0.3);
+        let rgb = sky.radiance(Vec3::new(0.0, 1.0, 0.0));
+
+        // Back to CIE XYZ from linear sRGB.
+        let cx = 0.4124 * rgb.x + 0.3576 * rgb.y + 0.1805 * rgb.z;
+        let cy = 0.2126 * rgb.x + 0.7152 * rgb.y + 0.0722 * rgb.z;
+        let cz = 0.0193 * rgb.x + 0.1192 * rgb.y + 0.9505 * rgb.z;
+        let sum = cx + cy + cz;
+
+        assert!((cy - 7.3204).abs() < 0.01 * 7.3204, "luminance {}", cy);
+        assert!((cx / sum - 0.2457).abs() < 0.002, "x {}", cx / sum);
+        assert!((cy / sum - 0.2515).abs() < 0.002, "y {}", cy / sum);
+    }
+
+    #[test]
+    fn test_sun_is_in_the_sky() {
+        for (elevation, azimuth) in [(5.0f64, 0.0), (30.0, 90.0), (60.0, -135.0), (90.0, 10.0)] {
+            let sun = PreethamSky::new(elevation, azimuth, 3.0, 1.0, 0.3)
+                .sun()
+                .unwrap();
+            // The light travels down from the sun's elevation.
+            let expected = -elevation.to_radians().sin();
+            assert!((sun.direction.y - expected).abs() < 1e-9);
+        }
+
+        let below = PreethamSky::new(-10.0, 0.0, 3.0, 1.0, 0.3);
+        assert!(below.sun().is_none());
+    }
+}